@@ -0,0 +1,135 @@
+//! Items saved to read later, kept apart from the seen items
+use crate::json::Json;
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bookmark {
+    pub id: String,
+    pub title: String,
+    pub link: String,
+    /// Name of the feed the item comes from
+    pub source: String,
+    pub date: Option<DateTime<FixedOffset>>,
+}
+
+impl Bookmark {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("id", Json::from(self.id.as_str())),
+            ("title", Json::from(self.title.as_str())),
+            ("link", Json::from(self.link.as_str())),
+            ("source", Json::from(self.source.as_str())),
+            (
+                "date",
+                Json::from(
+                    self.date
+                        .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, false)),
+                ),
+            ),
+        ])
+    }
+
+    fn from_json(value: &Json) -> Option<Self> {
+        let text = |key| value.get(key).and_then(Json::as_str).map(str::to_owned);
+        Some(Bookmark {
+            id: text("id")?,
+            title: text("title").unwrap_or_default(),
+            link: text("link")?,
+            source: text("source").unwrap_or_default(),
+            date: text("date").and_then(|date| DateTime::parse_from_rfc3339(&date).ok()),
+        })
+    }
+}
+
+/// Bookmarks in the order they were added, stored as JSON array of objects
+#[derive(Debug, Default, PartialEq)]
+pub struct Bookmarks {
+    pub items: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    /// Reads bookmarks from file. Missing file has no bookmarks
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => {
+                Bookmarks::parse(&text).map_err(|err| format!("{}: {}", path.display(), err))
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Bookmarks::default()),
+            Err(err) => Err(format!("Can't read {}: {}", path.display(), err)),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let json = Json::parse(text)?;
+        let values = json.as_array().ok_or("expected array of bookmarks")?;
+        let items = values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| {
+                Bookmark::from_json(value)
+                    .ok_or_else(|| format!("bookmark {} has no id or link", index + 1))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Bookmarks { items })
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.serialize())
+    }
+
+    pub fn serialize(&self) -> String {
+        let items = self.items.iter().map(Bookmark::to_json).collect();
+        format!("{}\n", Json::Array(items))
+    }
+
+    /// Adds bookmark unless one with the same id exists, returns whether it was added
+    pub fn add(&mut self, bookmark: Bookmark) -> bool {
+        if self.items.iter().any(|item| item.id == bookmark.id) {
+            return false;
+        }
+        self.items.push(bookmark);
+        true
+    }
+
+    /// Removes bookmark with `id`, returns whether there was one
+    pub fn remove(&mut self, id: &str) -> bool {
+        let count = self.items.len();
+        self.items.retain(|item| item.id != id);
+        self.items.len() != count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_round_trip() {
+        let mut bookmarks = Bookmarks::default();
+        let bookmark = Bookmark {
+            id: "urn:post:1".to_owned(),
+            title: "Say \"hi\"".to_owned(),
+            link: "https://example.com/1".to_owned(),
+            source: "Example".to_owned(),
+            date: DateTime::parse_from_rfc3339("2024-05-01T10:00:00+02:00").ok(),
+        };
+        assert!(bookmarks.add(bookmark.clone()));
+        assert!(!bookmarks.add(bookmark));
+        assert!(bookmarks.add(Bookmark {
+            id: "2".to_owned(),
+            title: String::new(),
+            link: "https://example.com/2".to_owned(),
+            source: String::new(),
+            date: None,
+        }));
+        assert_eq!(Bookmarks::parse(&bookmarks.serialize()), Ok(bookmarks));
+        assert_eq!(
+            Bookmarks::parse("[{\"title\": \"x\"}]"),
+            Err("bookmark 1 has no id or link".to_owned())
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! Settings file giving defaults of command line options. It's a subset of TOML:
+//! `key = value` lines with strings, integers, booleans and arrays, and `#` comments
+use std::path::Path;
+
+/// Defaults of command line options, options given on the command line win
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    /// `--sort` of `show-news` and `episodes`
+    pub sort: Option<String>,
+    /// `false` works like `--no-color`
+    pub color: Option<bool>,
+    pub concurrency: Option<usize>,
+    /// Duration like `30s`, see [`crate::parse_duration`]
+    pub timeout: Option<String>,
+    pub per_host_concurrency: Option<usize>,
+    /// Duration like `timeout`
+    pub per_host_interval: Option<String>,
+    /// OPML files read by commands given none
+    pub opml: Vec<String>,
+    /// `--timezone`, see [`crate::tz::TimeZone::lookup`]
+    pub timezone: Option<String>,
+    /// `--locale`, see [`crate::Locale::lookup`]
+    pub locale: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+        }
+    }
+
+    fn into_string(self) -> Result<String, String> {
+        match self {
+            Value::String(value) => Ok(value),
+            other => Err(format!("expected string, found {}", other.kind())),
+        }
+    }
+}
+
+impl Config {
+    /// Reads config from `path`, a missing file gives the default config
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text).map_err(|err| format!("{}: {}", path.display(), err)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(format!("Can't read {}: {}", path.display(), err)),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Config::default();
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                return Err(error("sections aren't supported".to_owned()));
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected `key = value`".to_owned()))?;
+            let key = key.trim();
+            let value = parse_value(value).map_err(error)?;
+            let setting_error = |message: String| error(format!("{}: {}", key, message));
+            match (key, value) {
+                ("sort", value) => config.sort = Some(value.into_string().map_err(setting_error)?),
+                ("color", Value::Boolean(value)) => config.color = Some(value),
+                ("concurrency", Value::Integer(value)) if value > 0 => {
+                    config.concurrency = Some(value as usize)
+                }
+                ("per_host_concurrency", Value::Integer(value)) if value > 0 => {
+                    config.per_host_concurrency = Some(value as usize)
+                }
+                ("timeout", value) => {
+                    let value = value.into_string().map_err(setting_error)?;
+                    crate::parse_duration(&value).map_err(setting_error)?;
+                    config.timeout = Some(value);
+                }
+                ("per_host_interval", value) => {
+                    let value = value.into_string().map_err(setting_error)?;
+                    crate::parse_duration(&value).map_err(setting_error)?;
+                    config.per_host_interval = Some(value);
+                }
+                ("timezone", value) => {
+                    let value = value.into_string().map_err(setting_error)?;
+                    crate::tz::TimeZone::lookup(&value).map_err(setting_error)?;
+                    config.timezone = Some(value);
+                }
+                ("locale", value) => {
+                    let value = value.into_string().map_err(setting_error)?;
+                    crate::Locale::lookup(&value).map_err(setting_error)?;
+                    config.locale = Some(value);
+                }
+                ("opml", Value::String(value)) => config.opml = vec![value],
+                ("opml", Value::Array(values)) => {
+                    config.opml = values
+                        .into_iter()
+                        .map(Value::into_string)
+                        .collect::<Result<_, _>>()
+                        .map_err(setting_error)?
+                }
+                ("color", value) => {
+                    return Err(setting_error(format!(
+                        "expected boolean, found {}",
+                        value.kind()
+                    )))
+                }
+                ("concurrency" | "per_host_concurrency", _) => {
+                    return Err(setting_error("expected positive integer".to_owned()))
+                }
+                ("opml", value) => {
+                    return Err(setting_error(format!(
+                        "expected string or array of strings, found {}",
+                        value.kind()
+                    )))
+                }
+                _ => return Err(error(format!("unknown setting '{}'", key))),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Parses value with optional trailing comment
+fn parse_value(text: &str) -> Result<Value, String> {
+    let mut chars = text.trim().chars().peekable();
+    let value = next_value(&mut chars)?;
+    skip_spaces(&mut chars);
+    match chars.next() {
+        None | Some('#') => Ok(value),
+        Some(c) => Err(format!("unexpected '{}' after value", c)),
+    }
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+fn skip_spaces(chars: &mut Chars) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn next_value(chars: &mut Chars) -> Result<Value, String> {
+    skip_spaces(chars);
+    match chars.peek() {
+        Some('"') | Some('\'') => next_string(chars).map(Value::String),
+        Some('[') => {
+            chars.next();
+            let mut values = Vec::new();
+            loop {
+                skip_spaces(chars);
+                if chars.next_if_eq(&']').is_some() {
+                    return Ok(Value::Array(values));
+                }
+                values.push(next_value(chars)?);
+                skip_spaces(chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some(']') => return Ok(Value::Array(values)),
+                    _ => return Err("expected ',' or ']' in array".to_owned()),
+                }
+            }
+        }
+        Some(_) => {
+            let mut word = String::new();
+            while let Some(c) =
+                chars.next_if(|c| c.is_alphanumeric() || matches!(c, '-' | '+' | '_'))
+            {
+                word.push(c);
+            }
+            match word.as_str() {
+                "true" => Ok(Value::Boolean(true)),
+                "false" => Ok(Value::Boolean(false)),
+                // bare words such as `desc` are a common slip, so suggest quoting them
+                _ => word
+                    .replace('_', "")
+                    .parse()
+                    .map(Value::Integer)
+                    .map_err(|_| format!("invalid value '{}', strings must be quoted", word)),
+            }
+        }
+        None => Err("missing value".to_owned()),
+    }
+}
+
+/// Reads `"basic"` string with escapes or `'literal'` string
+fn next_string(chars: &mut Chars) -> Result<String, String> {
+    let quote = chars.next();
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            None => return Err("unterminated string".to_owned()),
+            Some(c) if Some(c) == quote => return Ok(value),
+            Some('\\') if quote == Some('"') => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some(c @ ('"' | '\\')) => value.push(c),
+                Some(c) => return Err(format!("unknown escape '\\{}'", c)),
+                None => return Err("unterminated string".to_owned()),
+            },
+            Some(c) => value.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_settings() {
+        let config = Config::parse(
+            r#"
+            # defaults for every run
+            sort = "desc"
+            color = false   # plain output
+            concurrency = 4
+            timeout = '30s'
+            per_host_interval = "1s"
+            opml = ["~/feeds.opml", "C:\\work.opml"]
+            timezone = "+02:00"
+            locale = "de_DE"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                sort: Some("desc".to_owned()),
+                color: Some(false),
+                concurrency: Some(4),
+                timeout: Some("30s".to_owned()),
+                per_host_concurrency: None,
+                per_host_interval: Some("1s".to_owned()),
+                opml: vec!["~/feeds.opml".to_owned(), "C:\\work.opml".to_owned()],
+                timezone: Some("+02:00".to_owned()),
+                locale: Some("de_DE".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_errors_name_line() {
+        let error = |text| Config::parse(text).unwrap_err();
+        assert_eq!(
+            error("\nsort = desc"),
+            "line 2: invalid value 'desc', strings must be quoted"
+        );
+        assert_eq!(error("colour = false"), "line 1: unknown setting 'colour'");
+        assert_eq!(
+            error("color = \"no\""),
+            "line 1: color: expected boolean, found string"
+        );
+        assert_eq!(
+            error("timeout = \"soon\""),
+            "line 1: timeout: Invalid duration 'soon', expected e.g. 15m, 6h or 3d"
+        );
+        assert_eq!(
+            error("opml = [\"a\""),
+            "line 1: expected ',' or ']' in array"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! Error type of the library API
+use std::{fmt, io, path::PathBuf};
+
+/// Why reading OPML documents or feeds failed
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing file at `path` failed
+    Io { path: PathBuf, source: io::Error },
+    /// OPML document is malformed, the message names the line where possible
+    Opml {
+        path: Option<PathBuf>,
+        message: String,
+    },
+    /// Document isn't well-formed XML
+    XmlParse(String),
+    /// Feed or redirect URL can't be parsed
+    InvalidUrl { url: String, reason: String },
+    /// Request failed, e.g. because of the network or a timeout
+    Http(reqwest::Error),
+    /// Server responded with an error status, e.g. to a request for OPML
+    HttpStatus {
+        url: String,
+        status: reqwest::StatusCode,
+    },
+    /// Feed at the URL has no cached response to read it offline
+    NotCached(String),
+    /// Redirects didn't end within the allowed number of them
+    TooManyRedirects(usize),
+    /// Response body can't be decoded, e.g. because of an unsupported encoding
+    Decode(String),
+    /// Document is well-formed but isn't a feed of a supported format
+    FeedParse(String),
+    /// Feed item lacks a required element, e.g. `link`
+    MissingField(&'static str),
+    /// Date is in none of the known formats
+    DateParse(String),
+}
+
+impl Error {
+    /// Checks if request failed because of the network, so repeating it may help
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Http(err) => err.is_timeout() || err.is_connect() || err.is_request(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Opml {
+                path: Some(path),
+                message,
+            } => write!(f, "{}: {}", path.display(), message),
+            Error::Opml {
+                path: None,
+                message,
+            } => f.write_str(message),
+            Error::XmlParse(message) => write!(f, "Invalid XML: {}", message),
+            Error::InvalidUrl { url, reason } => write!(f, "Invalid URL '{}': {}", url, reason),
+            Error::Http(err) => write!(f, "{}", err),
+            Error::HttpStatus { url, status } => write!(f, "{}: HTTP {}", url, status),
+            Error::NotCached(url) => write!(f, "{} isn't cached, can't read it offline", url),
+            Error::TooManyRedirects(max) => {
+                write!(f, "Too many redirects, gave up after {}", max)
+            }
+            Error::Decode(message) | Error::FeedParse(message) => f.write_str(message),
+            Error::MissingField(field) => write!(f, "Item has no {}", field),
+            Error::DateParse(raw) => write!(f, "Invalid date '{}'", raw),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Http(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Http(err)
+    }
+}
+
+impl From<quick_xml::Error> for Error {
+    fn from(err: quick_xml::Error) -> Self {
+        Error::XmlParse(err.to_string())
+    }
+}
+
+impl From<rss::Error> for Error {
+    fn from(err: rss::Error) -> Self {
+        match err {
+            rss::Error::Xml(err) => Error::XmlParse(err.to_string()),
+            other => Error::FeedParse(other.to_string()),
+        }
+    }
+}
+
+impl From<atom_syndication::Error> for Error {
+    fn from(err: atom_syndication::Error) -> Self {
+        match err {
+            atom_syndication::Error::Xml(err) => Error::XmlParse(err.to_string()),
+            other => Error::FeedParse(other.to_string()),
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! Plain text of HTML found in feeds, and HTML entities
+
+/// HTML tags rendered as a word break when stripped
+const BLOCK_TAGS: &[&str] = &[
+    "address",
+    "article",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "img",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+/// HTML tags removed without a word break
+const INLINE_TAGS: &[&str] = &[
+    "a", "abbr", "b", "big", "cite", "code", "del", "em", "font", "i", "ins", "kbd", "mark", "q",
+    "s", "small", "span", "strike", "strong", "sub", "sup", "time", "tt", "u", "var", "wbr",
+];
+
+/// Converts HTML fragment to plain text: drops tags, decodes entities and collapses whitespace.
+/// Only known HTML tags are dropped, so text like `Vec<T>` stays intact
+pub fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[1..end];
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if tag.starts_with('!') || INLINE_TAGS.contains(&name.as_str()) {
+            rest = &rest[end + 1..];
+        } else if BLOCK_TAGS.contains(&name.as_str()) {
+            text.push(' ');
+            rest = &rest[end + 1..];
+        } else {
+            text.push('<');
+            rest = &rest[1..];
+        }
+    }
+    text.push_str(rest);
+    decode_entities(&text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Renders HTML of a post as plain text for reading. Paragraphs are separated by
+/// blank lines, headings are marked with `#`, list items with `-`, and links are
+/// followed by their URL
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    // URLs of the links being read, `None` for anchors without one
+    let mut links: Vec<Option<String>> = Vec::new();
+    let mut preformatted = 0usize;
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        push_text(&mut text, &rest[..start], preformatted > 0);
+        rest = &rest[start..];
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[1..end];
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let at_tag = rest;
+        rest = &rest[end + 1..];
+        match name.as_str() {
+            _ if tag.starts_with('!') => {}
+            "script" | "style" if !closing => {
+                let close = format!("</{}", name);
+                let skipped = rest.to_ascii_lowercase().find(&close).unwrap_or(rest.len());
+                rest = &rest[skipped..];
+            }
+            "script" | "style" => {}
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                break_line(&mut text, true);
+                if !closing {
+                    let level = usize::from(name.as_bytes()[1] - b'0');
+                    text.push_str(&"#".repeat(level));
+                    text.push(' ');
+                }
+            }
+            "pre" => {
+                break_line(&mut text, true);
+                preformatted = if closing {
+                    preformatted.saturating_sub(1)
+                } else {
+                    preformatted + 1
+                };
+            }
+            "li" if !closing => {
+                break_line(&mut text, false);
+                text.push_str("- ");
+            }
+            "br" | "tr" | "dt" | "dd" | "li" => break_line(&mut text, false),
+            "a" if closing => {
+                let Some(Some(href)) = links.pop() else {
+                    continue;
+                };
+                // links to themselves, like bare URLs, aren't repeated
+                if !text.trim_end().ends_with(&href) {
+                    text.push_str(&format!(" ({})", href));
+                }
+            }
+            "a" => links.push(
+                tag_attribute(tag, "href")
+                    .filter(|href| !href.is_empty() && !href.starts_with('#')),
+            ),
+            "img" if !closing => {
+                if let Some(alt) = tag_attribute(tag, "alt").filter(|alt| !alt.is_empty()) {
+                    push_text(&mut text, &format!("[{}]", alt), false);
+                }
+            }
+            name if BLOCK_TAGS.contains(&name) => break_line(&mut text, true),
+            name if INLINE_TAGS.contains(&name) => {}
+            _ => {
+                // not a tag, e.g. `a < b`, so keep it as text
+                text.push('<');
+                rest = &at_tag[1..];
+            }
+        }
+    }
+    push_text(&mut text, rest, preformatted > 0);
+    text.trim_end().to_owned()
+}
+
+/// Appends text of HTML, collapsing whitespace unless it is preformatted
+fn push_text(text: &mut String, html: &str, preformatted: bool) {
+    let decoded = decode_entities(html);
+    if preformatted {
+        text.push_str(&decoded);
+        return;
+    }
+    for (i, word) in decoded.split(char::is_whitespace).enumerate() {
+        let at_line_start = text.is_empty() || text.ends_with(['\n', ' ']);
+        if i > 0 && !at_line_start {
+            text.push(' ');
+        }
+        text.push_str(word);
+    }
+}
+
+/// Ends the current line, with `blank` also leaving an empty line after it
+fn break_line(text: &mut String, blank: bool) {
+    let trimmed = text.trim_end_matches(' ').len();
+    text.truncate(trimmed);
+    if text.is_empty() {
+        return;
+    }
+    let wanted = if blank { "\n\n" } else { "\n" };
+    while !text.ends_with(wanted) {
+        text.push('\n');
+    }
+}
+
+/// Value of attribute `name` in the text of an HTML tag, with entities decoded
+pub(crate) fn tag_attribute(tag: &str, name: &str) -> Option<String> {
+    let lowercase = tag.to_ascii_lowercase();
+    let mut search = 0;
+    let start = loop {
+        let found = search + lowercase[search..].find(name)?;
+        let before = lowercase[..found].chars().next_back();
+        let after = lowercase[found + name.len()..].trim_start();
+        if before.is_some_and(char::is_whitespace) && after.starts_with('=') {
+            break tag.len() - after.len() + 1;
+        }
+        search = found + name.len();
+    };
+    let value = tag[start..].trim_start();
+    let value = match value.chars().next() {
+        Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default(),
+        _ => value.split(char::is_whitespace).next().unwrap_or_default(),
+    };
+    Some(decode_entities(value.trim()))
+}
+
+/// Escapes plain text for use as HTML, turning blank lines into paragraphs
+pub(crate) fn text_to_html(text: &str) -> String {
+    let escaped = text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    escaped
+        .split("\n\n")
+        .map(|paragraph| format!("<p>{}</p>", paragraph.trim().replace('\n', "<br>")))
+        .collect()
+}
+
+/// Decodes numeric and the common named HTML entities, leaving unknown ones as is
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end + 1])?, end + 2)));
+        match entity {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        _ => return None,
+    };
+    Some(c)
+}
+
+/// Cuts text to `max` characters, marking the cut with an ellipsis
+pub fn truncate_chars(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", text[..end].trim_end()),
+        None => text.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_html_to_text() {
+        assert_eq!(
+            strip_html("<p>Hello <b>world</b></p><p>Second&nbsp;one &amp; more</p>"),
+            "Hello world Second one & more"
+        );
+        assert_eq!(strip_html("a < b"), "a < b");
+        assert_eq!(truncate_chars("hello world", 5), "hello…");
+        assert_eq!(truncate_chars("hello", 5), "hello");
+    }
+
+    #[test]
+    fn html_to_text_keeps_structure() {
+        let html = "<h2>Intro</h2><p>Read <a href=\"https://x.org/a?b=1&amp;c=2\">the  docs</a>\n\
+            first.<br>Then <a href=\"https://x.org\">https://x.org</a></p>\
+            <ul><li>one</li><li>two</li></ul><script>alert(1)</script>\
+            <pre>let x = 1;\n    x + 1</pre><p>a < b</p>";
+        assert_eq!(
+            html_to_text(html),
+            "## Intro\n\n\
+             Read the docs (https://x.org/a?b=1&c=2) first.\n\
+             Then https://x.org\n\n\
+             - one\n\
+             - two\n\n\
+             let x = 1;\n    x + 1\n\n\
+             a < b"
+        );
+        assert_eq!(text_to_html("a & b\n\nc"), "<p>a &amp; b</p><p>c</p>");
+    }
+}
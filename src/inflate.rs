@@ -0,0 +1,319 @@
+//! Decoder of compressed HTTP bodies: DEFLATE (RFC 1951) and its
+//! gzip (RFC 1952) and zlib (RFC 1950) wrappers
+
+/// Base lengths of length symbols 257..=285 and their extra bits
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distances of distance symbols 0..=29 and their extra bits
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which code length code lengths are stored in dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const TRUNCATED: &str = "Compressed data is truncated";
+
+/// Decompresses a gzip member
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    if !is_gzip(data) {
+        return Err("Not gzip data".to_owned());
+    }
+    if data.get(2) != Some(&8) {
+        return Err("Unknown gzip compression method".to_owned());
+    }
+    let flags = *data.get(3).ok_or(TRUNCATED)?;
+    let mut pos = 10;
+    if flags & FEXTRA != 0 {
+        let length = data.get(pos..pos + 2).ok_or(TRUNCATED)?;
+        pos += 2 + u16::from_le_bytes([length[0], length[1]]) as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let end = data
+                .get(pos..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0));
+            pos += end.ok_or(TRUNCATED)? + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+    inflate(data.get(pos..).ok_or(TRUNCATED)?)
+}
+
+/// Decompresses `deflate` content encoding. It's meant to be zlib wrapped,
+/// but some servers send raw DEFLATE, so both are accepted
+pub fn inflate_zlib(data: &[u8]) -> Result<Vec<u8>, String> {
+    match data {
+        [cmf, flg, rest @ ..]
+            if cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0 =>
+        {
+            inflate(rest)
+        }
+        _ => inflate(data),
+    }
+}
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&[0x1f, 0x8b])
+}
+
+/// Decompresses raw DEFLATE stream, ignoring anything after its last block
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut bits = Bits::new(data);
+    let mut out = Vec::with_capacity(data.len() * 4);
+    loop {
+        let last = bits.take(1)? == 1;
+        match bits.take(2)? {
+            0 => bits.stored(&mut out)?,
+            1 => {
+                let (lengths, distances) = fixed_codes();
+                bits.codes(&mut out, &lengths, &distances)?
+            }
+            2 => {
+                let (lengths, distances) = bits.dynamic_codes()?;
+                bits.codes(&mut out, &lengths, &distances)?
+            }
+            _ => return Err("Invalid DEFLATE block type".to_owned()),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+/// Canonical Huffman code given by number of codes of every length
+/// and symbols ordered by their codes
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, String> {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - i32::from(count);
+            if left < 0 {
+                return Err("Invalid Huffman code in compressed data".to_owned());
+            }
+        }
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [8u8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    let lengths = Huffman::new(&lengths).expect("fixed literal code is valid");
+    let distances = Huffman::new(&[5; 30]).expect("fixed distance code is valid");
+    (lengths, distances)
+}
+
+/// Reader of DEFLATE bit stream, least significant bit first
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Bits {
+            data,
+            pos: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn take(&mut self, count: u32) -> Result<u32, String> {
+        while self.count < count {
+            let byte = *self.data.get(self.pos).ok_or(TRUNCATED)?;
+            self.pos += 1;
+            self.buffer |= u32::from(byte) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << count) - 1);
+        self.buffer >>= count;
+        self.count -= count;
+        Ok(value)
+    }
+
+    fn decode(&mut self, code: &Huffman) -> Result<u16, String> {
+        let (mut value, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &code.counts[1..] {
+            value |= self.take(1)? as i32;
+            let count = i32::from(count);
+            if value - first < count {
+                return Ok(code.symbols[(index + value - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            value <<= 1;
+        }
+        Err("Invalid Huffman code in compressed data".to_owned())
+    }
+
+    fn stored(&mut self, out: &mut Vec<u8>) -> Result<(), String> {
+        // stored blocks start at a byte boundary, the buffer never holds a whole byte here
+        self.buffer = 0;
+        self.count = 0;
+        let header = self.data.get(self.pos..self.pos + 4).ok_or(TRUNCATED)?;
+        let length = u16::from_le_bytes([header[0], header[1]]);
+        if length != !u16::from_le_bytes([header[2], header[3]]) {
+            return Err("Invalid stored block length in compressed data".to_owned());
+        }
+        self.pos += 4;
+        let block = self
+            .data
+            .get(self.pos..self.pos + length as usize)
+            .ok_or(TRUNCATED)?;
+        out.extend_from_slice(block);
+        self.pos += length as usize;
+        Ok(())
+    }
+
+    fn dynamic_codes(&mut self) -> Result<(Huffman, Huffman), String> {
+        let literal_count = self.take(5)? as usize + 257;
+        let distance_count = self.take(5)? as usize + 1;
+        let code_length_count = self.take(4)? as usize + 4;
+        if literal_count > 286 || distance_count > 30 {
+            return Err("Invalid dynamic block header in compressed data".to_owned());
+        }
+        let mut code_lengths = [0u8; 19];
+        for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_lengths[index] = self.take(3)? as u8;
+        }
+        let code_length_code = Huffman::new(&code_lengths)?;
+
+        let mut lengths = Vec::with_capacity(literal_count + distance_count);
+        while lengths.len() < literal_count + distance_count {
+            let (value, repeat) = match self.decode(&code_length_code)? {
+                symbol @ 0..=15 => (symbol as u8, 1),
+                16 => {
+                    let previous = *lengths
+                        .last()
+                        .ok_or("Repeated code length without previous one")?;
+                    (previous, 3 + self.take(2)?)
+                }
+                17 => (0, 3 + self.take(3)?),
+                _ => (0, 11 + self.take(7)?),
+            };
+            if lengths.len() + repeat as usize > literal_count + distance_count {
+                return Err("Too many code lengths in compressed data".to_owned());
+            }
+            lengths.extend(std::iter::repeat_n(value, repeat as usize));
+        }
+        if lengths[256] == 0 {
+            return Err("Compressed block has no end code".to_owned());
+        }
+        let (literals, distances) = lengths.split_at(literal_count);
+        Ok((Huffman::new(literals)?, Huffman::new(distances)?))
+    }
+
+    fn codes(
+        &mut self,
+        out: &mut Vec<u8>,
+        lengths: &Huffman,
+        distances: &Huffman,
+    ) -> Result<(), String> {
+        loop {
+            let symbol = self.decode(lengths)? as usize;
+            if symbol < 256 {
+                out.push(symbol as u8);
+                continue;
+            }
+            if symbol == 256 {
+                return Ok(());
+            }
+            let symbol = symbol - 257;
+            if symbol >= LENGTH_BASE.len() {
+                return Err("Invalid length code in compressed data".to_owned());
+            }
+            let length =
+                LENGTH_BASE[symbol] as usize + self.take(u32::from(LENGTH_EXTRA[symbol]))? as usize;
+            let symbol = self.decode(distances)? as usize;
+            if symbol >= DISTANCE_BASE.len() {
+                return Err("Invalid distance code in compressed data".to_owned());
+            }
+            let distance = DISTANCE_BASE[symbol] as usize
+                + self.take(u32::from(DISTANCE_EXTRA[symbol]))? as usize;
+            if distance > out.len() {
+                return Err("Distance too far back in compressed data".to_owned());
+            }
+            // copies may overlap their own output, so go byte by byte
+            let start = out.len() - distance;
+            for i in 0..length {
+                out.push(out[start + i]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gunzip_skips_file_name() {
+        let data = [
+            0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0x66, 0x65, 0x65, 0x64,
+            0x2e, 0x78, 0x6d, 0x6c, 0x00, 0xb3, 0x29, 0x2a, 0x2e, 0xb6, 0xb3, 0xc9, 0x2c, 0x49,
+            0xcd, 0xb5, 0xcb, 0xcf, 0x4b, 0xb5, 0xd1, 0x07, 0xb3, 0x20, 0xfc, 0x92, 0xf2, 0x7c,
+            0x14, 0x3e, 0x92, 0xbc, 0x3e, 0x48, 0x17, 0x00, 0xd4, 0xc0, 0x47, 0xa4, 0x3b, 0x00,
+            0x00, 0x00,
+        ];
+        assert_eq!(
+            gunzip(&data).unwrap(),
+            b"<rss><item>one</item><item>two</item><item>one</item></rss>"
+        );
+        assert_eq!(gunzip(&data[..30]), Err(TRUNCATED.to_owned()));
+    }
+
+    #[test]
+    fn inflate_zlib_and_raw_streams() {
+        let stored = [
+            0x78, 0x01, 0x01, 0x06, 0x00, 0xf9, 0xff, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64, 0x09,
+            0x3c, 0x02, 0x92,
+        ];
+        assert_eq!(inflate_zlib(&stored).unwrap(), b"stored");
+        let fixed = [0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x00];
+        assert_eq!(inflate_zlib(&fixed).unwrap(), b"hello hello hello");
+    }
+}
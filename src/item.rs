@@ -0,0 +1,1124 @@
+//! Items of feeds, how they are shown and cleaned up
+use crate::feed::{atom_link, redact_url};
+use crate::html::{strip_html, text_to_html, truncate_chars};
+use crate::json::Json;
+use crate::time::{calendar_date_diff, date_diff, parse_date, Locale};
+use crate::{tz, Error};
+use chrono::{DateTime, FixedOffset};
+use colored::*;
+use rss::Item;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+#[derive(Debug)]
+pub struct FeedItem {
+    guid: Option<String>,
+    pub title: String,
+    pub link: String,
+    /// Link given by the feed when `link` was changed with [`FeedItem::set_link`]
+    pub original_link: Option<String>,
+    /// Publication date, `None` when neither the item nor its feed has one
+    pub pub_date: Option<DateTime<FixedOffset>>,
+    pub source_name: String,
+    pub source_url: String,
+    /// URL the feed of the item was fetched from, unlike `source_url` telling apart
+    /// feeds of one site
+    pub feed_url: String,
+    /// Summary of the item as plain text
+    pub description: Option<String>,
+    /// Full content as HTML, e.g. from `content:encoded`, see [`html_to_text`](crate::html_to_text)
+    pub content: Option<String>,
+    /// Name of the author, or their email when the feed gives only that
+    pub author: Option<String>,
+    /// Tags of the item, e.g. RSS `category` elements, in the order of the feed
+    pub categories: Vec<String>,
+    /// Attached media file, e.g. audio of a podcast episode
+    pub enclosure: Option<Enclosure>,
+    /// URL of a thumbnail or other picture of the item
+    pub image: Option<String>,
+    /// Language its feed declares, e.g. `en-us`
+    pub source_language: Option<String>,
+    /// Copyright notice of its feed
+    pub copyright: Option<String>,
+}
+
+/// Media file attached to an item
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enclosure {
+    pub url: String,
+    pub mime_type: Option<String>,
+    /// Size in bytes
+    pub length: Option<u64>,
+}
+
+/// File extensions of audio and video, for enclosures without MIME type
+const MEDIA_EXTENSIONS: &[&str] = &[
+    "aac", "flac", "m4a", "m4v", "mov", "mp3", "mp4", "oga", "ogg", "opus", "wav", "webm",
+];
+
+/// File extensions of pictures, for enclosures without MIME type
+const IMAGE_EXTENSIONS: &[&str] = &["avif", "gif", "jpeg", "jpg", "png", "svg", "webp"];
+
+/// Checks if path of `url` ends with one of `extensions`
+fn has_extension(url: &str, extensions: &[&str]) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    path.rsplit_once('.')
+        .is_some_and(|(_, extension)| extensions.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+impl Enclosure {
+    fn new(url: &str, mime_type: Option<&str>, length: Option<&str>) -> Option<Self> {
+        let url = url.trim();
+        if url.is_empty() {
+            return None;
+        }
+        Some(Enclosure {
+            url: url.to_owned(),
+            mime_type: mime_type
+                .map(str::trim)
+                .filter(|mime_type| !mime_type.is_empty())
+                .map(str::to_owned),
+            length: length.and_then(|length| length.trim().parse().ok()),
+        })
+    }
+
+    /// Checks if enclosure is audio or video, by MIME type or else by file extension
+    pub fn is_media(&self) -> bool {
+        if let Some(mime_type) = &self.mime_type {
+            return mime_type.starts_with("audio/") || mime_type.starts_with("video/");
+        }
+        has_extension(&self.url, MEDIA_EXTENSIONS)
+    }
+
+    /// Checks if enclosure is a picture, by MIME type or else by file extension
+    pub fn is_image(&self) -> bool {
+        match &self.mime_type {
+            Some(mime_type) => mime_type.starts_with("image/"),
+            None => has_extension(&self.url, IMAGE_EXTENSIONS),
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object([
+            ("url", Json::from(self.url.as_str())),
+            ("mime_type", Json::from(self.mime_type.as_deref())),
+            (
+                "length",
+                self.length
+                    .map_or(Json::Null, |length| Json::Number(length as f64)),
+            ),
+        ])
+    }
+}
+
+impl fmt::Display for Enclosure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.url)?;
+        match (&self.mime_type, self.length) {
+            (Some(mime_type), Some(length)) => {
+                write!(f, " ({}, {})", mime_type, format_size(length))
+            }
+            (Some(mime_type), None) => write!(f, " ({})", mime_type),
+            (None, Some(length)) => write!(f, " ({})", format_size(length)),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Formats byte count as e.g. `512 B`, `3.4 MB`
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while size >= 1000.0 && unit + 1 < UNITS.len() {
+        size /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Settings of [`FeedItem::show`]
+#[derive(Debug, Default, Clone)]
+pub struct ShowOptions {
+    /// Print description under the title, cut to this many characters
+    pub description_length: Option<usize>,
+    /// Omit source name, e.g. when items are listed under a header of their source
+    pub hide_source: bool,
+    /// Print author after the title
+    pub show_author: bool,
+    /// Print URL of attached media under the title
+    pub show_enclosure: bool,
+    pub date_style: DateStyle,
+    /// strftime pattern of absolute dates, [`DEFAULT_DATE_PATTERN`] when not set
+    pub date_pattern: Option<String>,
+    /// Time zone of absolute dates, local time when not set
+    pub timezone: Option<tz::TimeZone>,
+    /// Language of relative dates
+    pub locale: Locale,
+    /// Show titles in all caps in sentence case, see [`normalize_title`]
+    pub normalize_titles: bool,
+}
+
+/// How dates of items are shown
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum DateStyle {
+    /// `3 days ago`
+    #[default]
+    Relative,
+    /// `2024-01-15 10:00`
+    Absolute,
+    /// `2024-01-15 10:00, 3 days ago`
+    Both,
+    /// `today, 14:05`, `yesterday, 14:05`, then `3 days ago`
+    Calendar,
+}
+
+pub const DEFAULT_DATE_PATTERN: &str = "%Y-%m-%d %H:%M";
+
+/// Checks that strftime `pattern` is valid, as formatting with an invalid one panics
+pub fn parse_date_pattern(pattern: &str) -> Result<String, String> {
+    let invalid =
+        chrono::format::StrftimeItems::new(pattern).any(|item| item == chrono::format::Item::Error);
+    if invalid {
+        return Err(format!("Invalid date pattern '{}'", pattern));
+    }
+    Ok(pattern.to_owned())
+}
+
+/// Element of an extension, e.g. Media RSS, which the RSS and Atom parsers keep
+/// in types of their own
+trait ExtensionElement: Sized {
+    fn attr(&self, name: &str) -> Option<&str>;
+    fn children(&self) -> &BTreeMap<String, Vec<Self>>;
+}
+
+impl ExtensionElement for rss::extension::Extension {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs().get(name).map(String::as_str)
+    }
+
+    fn children(&self) -> &BTreeMap<String, Vec<Self>> {
+        self.children()
+    }
+}
+
+impl ExtensionElement for atom_syndication::extension::Extension {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs().get(name).map(String::as_str)
+    }
+
+    fn children(&self) -> &BTreeMap<String, Vec<Self>> {
+        self.children()
+    }
+}
+
+/// Finds picture in Media RSS elements: a `media:thumbnail`, else a `media:content`
+/// that is an image, else the same within a `media:group`
+fn media_image<E: ExtensionElement>(media: &BTreeMap<String, Vec<E>>) -> Option<String> {
+    let elements = |name| media.get(name).into_iter().flatten();
+    let url = |element: &E| {
+        Some(element.attr("url")?.trim())
+            .filter(|url| !url.is_empty())
+            .map(str::to_owned)
+    };
+    let is_image = |element: &&E| match (element.attr("medium"), element.attr("type")) {
+        (Some(medium), _) => medium == "image",
+        (None, Some(mime_type)) => mime_type.starts_with("image/"),
+        (None, None) => element
+            .attr("url")
+            .is_some_and(|url| has_extension(url, IMAGE_EXTENSIONS)),
+    };
+    elements("thumbnail")
+        .find_map(url)
+        .or_else(|| elements("content").filter(is_image).find_map(url))
+        .or_else(|| elements("group").find_map(|group| media_image(group.children())))
+        .or_else(|| {
+            // thumbnails of videos and other media are nested in their `media:content`
+            elements("content").find_map(|content| media_image(content.children()))
+        })
+}
+
+/// Length of titles made from text of untitled JSON Feed items
+const JSON_TITLE_LENGTH: usize = 80;
+
+/// Trims tags of an item, dropping empty ones and repeats
+fn tag_names<'a>(names: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for name in names.map(str::trim).filter(|name| !name.is_empty()) {
+        if !tags
+            .iter()
+            .any(|tag| tag.to_lowercase() == name.to_lowercase())
+        {
+            tags.push(name.to_owned());
+        }
+    }
+    tags
+}
+
+impl FeedItem {
+    /// Builds item from RSS item. Items without date get `pub_date` from their `dc:date`,
+    /// otherwise `default_date`, which is usually the date of the channel
+    pub fn make(
+        item: &Item,
+        source_name: &str,
+        source_link: &str,
+        default_date: Option<DateTime<FixedOffset>>,
+    ) -> Result<Self, Error> {
+        let guid = item.guid().map(|x| x.value.clone());
+        let title = item
+            .title()
+            .map(clean_title)
+            .ok_or(Error::MissingField("title"))?;
+        let link = item
+            .link()
+            .map(|s| s.to_owned())
+            .ok_or(Error::MissingField("link"))?;
+        let dc_date = item
+            .dublin_core_ext()
+            .and_then(|dc| dc.dates().first())
+            .map(|date| date.as_str());
+        let pub_date = match item.pub_date().or(dc_date) {
+            Some(raw_pub_date) => Some(parse_date(raw_pub_date)?),
+            None => default_date,
+        };
+        let dc_creator = item
+            .dublin_core_ext()
+            .and_then(|dc| dc.creators().first())
+            .map(|creator| creator.as_str());
+        let author = item
+            .author()
+            .and_then(author_name)
+            .or_else(|| dc_creator.and_then(author_name));
+        let dc_subjects = item
+            .dublin_core_ext()
+            .map_or(&[][..], |dc| dc.subjects())
+            .iter()
+            .map(String::as_str);
+        let names = item.categories().iter().map(|category| category.name());
+        let categories = tag_names(names.chain(dc_subjects));
+        let enclosure = item.enclosure().and_then(|enclosure| {
+            Enclosure::new(
+                enclosure.url(),
+                Some(enclosure.mime_type()),
+                Some(enclosure.length()),
+            )
+        });
+        let image = item
+            .extensions()
+            .get("media")
+            .and_then(media_image)
+            .or_else(|| {
+                let image = item.itunes_ext()?.image()?.trim();
+                Some(image.to_owned()).filter(|image| !image.is_empty())
+            })
+            .or_else(|| {
+                let enclosure = enclosure
+                    .as_ref()
+                    .filter(|enclosure| enclosure.is_image())?;
+                Some(enclosure.url.clone())
+            });
+        Ok(FeedItem {
+            guid,
+            title,
+            link,
+            original_link: None,
+            pub_date,
+            source_name: source_name.to_owned(),
+            source_url: source_link.to_owned(),
+            feed_url: String::new(),
+            description: item.description().map(strip_html),
+            content: item
+                .content()
+                .filter(|content| !content.trim().is_empty())
+                .map(|content| content.to_owned()),
+            author,
+            categories,
+            enclosure,
+            image,
+            source_language: None,
+            copyright: None,
+        })
+    }
+
+    pub fn from_entry(
+        entry: &atom_syndication::Entry,
+        source_name: &str,
+        source_link: &str,
+    ) -> Result<Self, Error> {
+        let link = atom_link(entry.links())
+            .map(|s| s.to_owned())
+            .ok_or(Error::MissingField("link"))?;
+        let enclosures: Vec<Enclosure> = entry
+            .links()
+            .iter()
+            .filter(|link| link.rel() == "enclosure")
+            .filter_map(|link| Enclosure::new(link.href(), link.mime_type(), link.length()))
+            .collect();
+        let image = entry
+            .extensions()
+            .get("media")
+            .and_then(media_image)
+            .or_else(|| {
+                let enclosure = enclosures.iter().find(|enclosure| enclosure.is_image())?;
+                Some(enclosure.url.clone())
+            });
+        Ok(FeedItem {
+            guid: Some(entry.id().to_owned()).filter(|id| !id.is_empty()),
+            title: clean_title(entry.title().as_str()),
+            link,
+            original_link: None,
+            pub_date: Some(*entry.updated()),
+            source_name: source_name.to_owned(),
+            source_url: source_link.to_owned(),
+            feed_url: String::new(),
+            description: entry.summary().map(|summary| match summary.r#type {
+                atom_syndication::TextType::Text => summary.value.clone(),
+                _ => strip_html(&summary.value),
+            }),
+            content: entry
+                .content()
+                .and_then(|content| {
+                    let value = content.value()?;
+                    Some(match content.content_type() {
+                        Some("html" | "xhtml" | "text/html") => value.to_owned(),
+                        _ => text_to_html(value),
+                    })
+                })
+                .filter(|content| !content.trim().is_empty()),
+            author: entry.authors().iter().find_map(|person| {
+                author_name(&person.name).or_else(|| author_name(person.email()?))
+            }),
+            categories: tag_names(
+                entry
+                    .categories()
+                    .iter()
+                    .map(|category| category.label().unwrap_or(category.term())),
+            ),
+            enclosure: enclosures.into_iter().next(),
+            image,
+            source_language: None,
+            copyright: None,
+        })
+    }
+
+    /// Builds item from JSON Feed item. Items without title, e.g. microblog posts,
+    /// are titled with the beginning of their text
+    pub fn from_json(item: &Json, source_name: &str, source_link: &str) -> Result<Self, Error> {
+        let text = |key| {
+            item.get(key)
+                .and_then(Json::as_str)
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        let link = text("url")
+            .or(text("external_url"))
+            .map(|s| s.to_owned())
+            .ok_or(Error::MissingField("link"))?;
+        let description = text("summary")
+            .or(text("content_text"))
+            .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+            .or_else(|| text("content_html").map(strip_html))
+            .filter(|description| !description.is_empty());
+        let title = match text("title") {
+            Some(title) => clean_title(title),
+            None => description
+                .as_deref()
+                .map(|description| truncate_chars(description, JSON_TITLE_LENGTH))
+                .ok_or(Error::MissingField("title"))?,
+        };
+        let pub_date = text("date_published")
+            .or(text("date_modified"))
+            .map(parse_date)
+            .transpose()?;
+        let guid = match item.get("id") {
+            Some(Json::String(id)) => Some(id.clone()),
+            // 1.0 feeds sometimes have numeric ids
+            Some(Json::Number(id)) => Some(id.to_string()),
+            _ => None,
+        };
+        let author = item
+            .get("authors")
+            .and_then(Json::as_array)
+            .and_then(|authors| authors.first())
+            .or(item.get("author"))
+            .and_then(|author| author.get("name")?.as_str())
+            .and_then(author_name);
+        let enclosure = item
+            .get("attachments")
+            .and_then(Json::as_array)
+            .and_then(|attachments| attachments.first())
+            .and_then(|attachment| {
+                let length = attachment.get("size_in_bytes").and_then(Json::as_f64);
+                Enclosure::new(
+                    attachment.get("url")?.as_str()?,
+                    attachment.get("mime_type").and_then(Json::as_str),
+                    length.map(|length| length.to_string()).as_deref(),
+                )
+            });
+        let image = text("image")
+            .or(text("banner_image"))
+            .map(str::to_owned)
+            .or_else(|| {
+                let enclosure = enclosure
+                    .as_ref()
+                    .filter(|enclosure| enclosure.is_image())?;
+                Some(enclosure.url.clone())
+            });
+        Ok(FeedItem {
+            guid,
+            title,
+            link,
+            original_link: None,
+            pub_date,
+            source_name: source_name.to_owned(),
+            source_url: source_link.to_owned(),
+            feed_url: String::new(),
+            description,
+            content: text("content_html")
+                .map(|html| html.to_owned())
+                .or_else(|| text("content_text").map(text_to_html)),
+            author,
+            categories: tag_names(
+                item.get("tags")
+                    .and_then(Json::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Json::as_str),
+            ),
+            enclosure,
+            image,
+            source_language: None,
+            copyright: None,
+        })
+    }
+
+    /// Returns guid of the item. If not found, then constructs pseudo guid from the
+    /// normalized original link, so fixing a typo in the title doesn't make it new
+    pub fn get_id(&self) -> String {
+        let link = self.original_link.as_ref().unwrap_or(&self.link);
+        self.guid.clone().unwrap_or_else(|| normalize_url(link))
+    }
+
+    /// Pseudo guid of items without one in older versions, made of title and link,
+    /// see [`SeenStore::adopt_legacy_ids`](crate::SeenStore::adopt_legacy_ids)
+    pub fn legacy_id(&self) -> Option<String> {
+        let link = self.original_link.as_ref().unwrap_or(&self.link);
+        self.guid
+            .is_none()
+            .then(|| format!("{}-{}", self.title, link))
+    }
+
+    /// Keeps title in the pseudo guid of the item, for feeds whose items without
+    /// guids share a link
+    pub(crate) fn keep_title_in_id(&mut self) {
+        self.guid = self.legacy_id();
+    }
+
+    /// Replaces link of the item, keeping the one given by the feed in `original_link`
+    pub fn set_link(&mut self, link: String) {
+        if link != self.link {
+            let original = std::mem::replace(&mut self.link, link);
+            self.original_link.get_or_insert(original);
+        }
+    }
+
+    /// Checks if the item is tagged with any of `tags`, ignoring case
+    pub fn has_any_tag(&self, tags: &[String]) -> bool {
+        self.categories.iter().any(|category| {
+            tags.iter()
+                .any(|tag| category.to_lowercase() == tag.to_lowercase())
+        })
+    }
+
+    /// Checks if its feed is in any of `languages`, ignoring case. A language without
+    /// region like `en` covers its regional variants like `en-US` and `en_GB`
+    pub fn in_any_language(&self, languages: &[String]) -> bool {
+        let Some(source) = &self.source_language else {
+            return false;
+        };
+        let source = source.to_lowercase().replace('_', "-");
+        languages.iter().any(|language| {
+            let language = language.to_lowercase().replace('_', "-");
+            source
+                .strip_prefix(&language)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+        })
+    }
+
+    /// Checks if `matches` accepts the title, or the description when `with_description`
+    pub fn text_matches(&self, with_description: bool, matches: impl Fn(&str) -> bool) -> bool {
+        matches(&self.title) || with_description && self.description.as_deref().is_some_and(matches)
+    }
+
+    pub fn to_json(&self, already_seen: bool) -> Json {
+        Json::object([
+            ("id", Json::from(self.get_id())),
+            ("title", Json::from(self.title.as_str())),
+            ("link", Json::from(self.link.as_str())),
+            ("original_link", Json::from(self.original_link.as_deref())),
+            (
+                "pub_date",
+                Json::from(self.pub_date.map(|date| date.to_rfc3339())),
+            ),
+            ("source_name", Json::from(self.source_name.as_str())),
+            (
+                "source_url",
+                Json::from(redact_url(&self.source_url).into_owned()),
+            ),
+            (
+                "feed_url",
+                Json::from(redact_url(&self.feed_url).into_owned()),
+            ),
+            (
+                "source_language",
+                Json::from(self.source_language.as_deref()),
+            ),
+            ("copyright", Json::from(self.copyright.as_deref())),
+            ("author", Json::from(self.author.as_deref())),
+            (
+                "categories",
+                Json::Array(
+                    self.categories
+                        .iter()
+                        .map(|tag| Json::from(tag.as_str()))
+                        .collect(),
+                ),
+            ),
+            (
+                "enclosure",
+                self.enclosure
+                    .as_ref()
+                    .map_or(Json::Null, Enclosure::to_json),
+            ),
+            ("image", Json::from(self.image.as_deref())),
+            ("seen", Json::from(already_seen)),
+        ])
+    }
+
+    /// Formats publication date in the style of `options`
+    pub fn format_date(&self, now: DateTime<FixedOffset>, options: &ShowOptions) -> String {
+        let Some(pub_date) = self.pub_date else {
+            return "date unknown".to_owned();
+        };
+        let absolute = || {
+            let pattern = options
+                .date_pattern
+                .as_deref()
+                .unwrap_or(DEFAULT_DATE_PATTERN);
+            match &options.timezone {
+                Some(timezone) => timezone.convert(&pub_date).format(pattern).to_string(),
+                None => pub_date
+                    .with_timezone(&chrono::Local)
+                    .format(pattern)
+                    .to_string(),
+            }
+        };
+        match options.date_style {
+            DateStyle::Relative => date_diff(now - pub_date, options.locale),
+            DateStyle::Absolute => absolute(),
+            DateStyle::Both => {
+                let relative = date_diff(now - pub_date, options.locale);
+                format!("{}, {}", absolute(), relative)
+            }
+            DateStyle::Calendar => {
+                let now = match &options.timezone {
+                    Some(timezone) => timezone.convert(&now),
+                    None => now.with_timezone(&chrono::Local).fixed_offset(),
+                };
+                calendar_date_diff(pub_date, now, options.locale)
+            }
+        }
+    }
+
+    pub fn show(&self, now: DateTime<FixedOffset>, already_seen: bool, options: &ShowOptions) {
+        print!("{}", self.render(now, already_seen, options));
+    }
+
+    /// Formats lines [`FeedItem::show`] prints, each ending with a newline
+    pub fn render(
+        &self,
+        now: DateTime<FixedOffset>,
+        already_seen: bool,
+        options: &ShowOptions,
+    ) -> String {
+        let link = self.link.as_str();
+        let dt_ago = self.format_date(now, options);
+        let title = if options.normalize_titles {
+            normalize_title(&self.title)
+        } else {
+            Cow::Borrowed(self.title.as_str())
+        };
+        let (label, title) = match (options.hide_source, already_seen) {
+            (false, true) => (format!("{}: ", self.source_name), title.hidden()),
+            (false, false) => (format!("{} (*new*): ", self.source_name), title.bold()),
+            (true, true) => ("  ".to_owned(), title.hidden()),
+            (true, false) => ("  (*new*) ".to_owned(), title.bold()),
+        };
+        let author = match &self.author {
+            Some(author) if options.show_author => format!(" by {}", author),
+            _ => String::new(),
+        };
+        let mut lines = format!(
+            "{}{}{} ({}) {}\n",
+            label,
+            title,
+            author,
+            dt_ago.dimmed(),
+            link
+        );
+        if let (true, Some(enclosure)) = (options.show_enclosure, &self.enclosure) {
+            lines.push_str(&format!("    {} {}\n", "media:".dimmed(), enclosure));
+        }
+        if let (Some(length), Some(description)) = (options.description_length, &self.description) {
+            if !description.is_empty() {
+                lines.push_str(&format!("    {}\n", truncate_chars(description, length)));
+            }
+        }
+        lines
+    }
+
+    /// Formats item as `title<TAB>link` line for scripts
+    pub fn render_quiet(&self) -> String {
+        format!("{}\t{}\n", self.title.replace(['\t', '\n'], " "), self.link)
+    }
+}
+
+/// Formats item as Markdown list item `- [title](link) — source (date)`, without the
+/// source when `hide_source`, e.g. under a header of it
+pub fn render_markdown(
+    item: &FeedItem,
+    now: DateTime<FixedOffset>,
+    hide_source: bool,
+    locale: Locale,
+) -> String {
+    let date = match item.pub_date {
+        Some(pub_date) => date_diff(now - pub_date, locale),
+        None => "date unknown".to_owned(),
+    };
+    let source = if hide_source {
+        String::new()
+    } else {
+        format!(" — {}", escape_markdown(&item.source_name))
+    };
+    format!(
+        "- [{}]({}){} ({})\n",
+        escape_markdown(&item.title),
+        markdown_link(&item.link),
+        source,
+        date
+    )
+}
+
+/// Header row of [`render_csv`] rows
+pub const CSV_HEADER: &str = "pub_date,source_name,title,link,seen\r\n";
+
+/// Formats item as RFC 4180 CSV row of the [`CSV_HEADER`] columns, the date in RFC 3339
+pub fn render_csv(item: &FeedItem, already_seen: bool) -> String {
+    let date = item
+        .pub_date
+        .map(|date| date.to_rfc3339_opts(chrono::SecondsFormat::Secs, false))
+        .unwrap_or_default();
+    format!(
+        "{},{},{},{},{}\r\n",
+        date,
+        csv_field(&item.source_name),
+        csv_field(&item.title),
+        csv_field(&item.link),
+        already_seen
+    )
+}
+
+/// Quotes field containing commas, quotes or line breaks, doubling its quotes
+fn csv_field(text: &str) -> Cow<'_, str> {
+    if text.contains([',', '"', '\r', '\n']) {
+        Cow::Owned(format!("\"{}\"", text.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Escapes characters that Markdown would read as formatting
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '(' | ')' | '#' | '<' | '>' | '!' | '|' | '~'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Percent-encodes characters that would end the link target early
+fn markdown_link(link: &str) -> String {
+    link.replace(' ', "%20")
+        .replace('(', "%28")
+        .replace(')', "%29")
+        .replace('<', "%3C")
+        .replace('>', "%3E")
+}
+
+/// Extracts author name from `email (Name)` form used by RSS, other forms are kept as is
+fn author_name(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    let name = raw
+        .strip_suffix(')')
+        .and_then(|rest| rest.split_once('('))
+        .filter(|(email, _)| email.contains('@'))
+        .map_or(raw, |(_, name)| name.trim());
+    Some(name.to_owned()).filter(|name| !name.is_empty())
+}
+
+/// Cleans up titles with HTML markup or entities, leaving plain titles untouched
+pub fn clean_title(title: &str) -> String {
+    if title.contains(['<', '&']) {
+        strip_html(title)
+    } else {
+        title.to_owned()
+    }
+}
+
+/// Titles with fewer letters are left in all caps, e.g. `BREAKING`
+const MIN_SHOUTING_LETTERS: usize = 10;
+
+/// Acronyms kept in all caps by [`normalize_title`] though they have vowels
+const ACRONYMS: &[&str] = &[
+    "AI", "API", "CEO", "CPU", "EU", "FAQ", "GPU", "IOS", "NASA", "OS", "UI", "UK", "UN", "URL",
+    "USA",
+];
+
+/// Converts title in all caps to sentence case, leaving titles with lowercase letters
+/// and short ones as is. Words with digits or without vowels, e.g. `HTML5` or `BBC`,
+/// and known acronyms like `NASA` are taken for acronyms and kept
+pub fn normalize_title(title: &str) -> Cow<'_, str> {
+    let letters = title.chars().filter(|c| c.is_alphabetic()).count();
+    if letters < MIN_SHOUTING_LETTERS || title.chars().any(char::is_lowercase) {
+        return Cow::Borrowed(title);
+    }
+    let mut normalized = String::with_capacity(title.len());
+    let mut sentence_start = true;
+    for (index, word) in title.split(' ').enumerate() {
+        if index > 0 {
+            normalized.push(' ');
+        }
+        let core: String = word.chars().filter(|c| c.is_alphanumeric()).collect();
+        let is_acronym = ACRONYMS.contains(&core.as_str())
+            || core.chars().any(|c| c.is_numeric())
+            || !core.is_empty() && !core.contains(['A', 'E', 'I', 'O', 'U', 'Y']);
+        if is_acronym {
+            normalized.push_str(word);
+        } else {
+            let lower = word.to_lowercase();
+            // `I` and `I'm` stay capitalized within sentences too
+            let mut capitalize = sentence_start || core == "I" || word.starts_with("I'");
+            for c in lower.chars() {
+                if capitalize && c.is_alphabetic() {
+                    normalized.extend(c.to_uppercase());
+                    capitalize = false;
+                } else {
+                    normalized.push(c);
+                }
+            }
+        }
+        if !core.is_empty() {
+            sentence_start = word.ends_with(['.', '!', '?', ':']);
+        }
+    }
+    Cow::Owned(normalized)
+}
+
+/// Removes repeated items keeping the first one. Items are the same when they have
+/// the same id, or the same title and link after normalization. Guids are compared
+/// only within a feed, as feeds may number their items alike, and items without guid
+/// by their resolved link, as ids keep relative links feeds gave
+pub fn dedup_items(items: &mut Vec<FeedItem>) {
+    let mut ids = HashSet::new();
+    let mut links = HashSet::new();
+    items.retain(|item| {
+        let id = match &item.guid {
+            Some(guid) => format!("{}\t{}", item.feed_url, guid),
+            None => normalize_url(&item.link),
+        };
+        let link = (item.title.to_lowercase(), normalize_url(&item.link));
+        // both sets must learn about the item, so no short-circuiting here
+        let new_id = ids.insert(id);
+        let new_link = links.insert(link);
+        new_id & new_link
+    });
+}
+
+/// Query parameters of links that only tell where readers came from, besides `utm_*`
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "dclid", "msclkid", "yclid", "igshid", "mc_cid", "mc_eid", "_hsenc",
+    "_hsmi", "mkt_tok",
+];
+
+/// Hosts of FeedBurner, whose item links redirect to the actual articles
+const FEEDPROXY_HOSTS: &[&str] = &["feedproxy.google.com", "feeds.feedburner.com"];
+
+/// Removes `utm_*` and other tracking parameters from the query of `url`.
+/// URLs without them are returned as is
+pub fn strip_tracking_params(url: &str) -> String {
+    let Ok(mut parsed) = reqwest::Url::parse(url) else {
+        return url.to_owned();
+    };
+    let Some(query) = parsed.query().map(str::to_owned) else {
+        return url.to_owned();
+    };
+    let is_tracking = |pair: &&str| {
+        let name = pair.split('=').next().unwrap_or_default().to_lowercase();
+        name.starts_with("utm_") || TRACKING_PARAMS.contains(&name.as_str())
+    };
+    let pairs: Vec<&str> = query.split('&').collect();
+    if !pairs.iter().any(is_tracking) {
+        return url.to_owned();
+    }
+    let kept: Vec<&str> = pairs
+        .into_iter()
+        .filter(|pair| !is_tracking(pair))
+        .collect();
+    if kept.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.set_query(Some(&kept.join("&")));
+    }
+    parsed.to_string()
+}
+
+/// Checks if `url` is a FeedBurner link redirecting to the article
+pub fn is_feedproxy_link(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|parsed| {
+        parsed
+            .host_str()
+            .is_some_and(|host| FEEDPROXY_HOSTS.contains(&host))
+    })
+}
+
+/// Checks if `text` contains `needle` ignoring case and allowing a typo per four
+/// characters of `needle`, that is if some run of words of `text` is that close to it
+pub fn fuzzy_contains(text: &str, needle: &str) -> bool {
+    let (text, needle) = (text.to_lowercase(), needle.to_lowercase());
+    if text.contains(&needle) {
+        return true;
+    }
+    let words = |text: &str| -> Vec<String> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_owned)
+            .collect()
+    };
+    let needle = words(&needle);
+    if needle.is_empty() {
+        return true;
+    }
+    let needle = needle.join(" ");
+    let allowed = needle.chars().count() / 4;
+    words(&text)
+        .windows(needle.split(' ').count())
+        .any(|window| edit_distance(&window.join(" "), &needle) <= allowed)
+}
+
+/// Levenshtein distance of `a` and `b` in characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &b) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a != b);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Normalizes URL for comparison: ignores scheme, case of host, fragment and trailing slash
+pub fn normalize_url(url: &str) -> String {
+    let Ok(mut parsed) = reqwest::Url::parse(url.trim()) else {
+        return url.trim().trim_end_matches('/').to_owned();
+    };
+    parsed.set_fragment(None);
+    let normalized = parsed.as_str();
+    let without_scheme = normalized
+        .split_once("://")
+        .map_or(normalized, |(_, rest)| rest);
+    without_scheme.trim_end_matches('/').to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_markdown_escapes_title() {
+        let now = DateTime::parse_from_rfc3339("2024-05-03T10:00:00Z").unwrap();
+        let item = FeedItem {
+            guid: None,
+            title: "[RFC] *Async* traits_v2".to_owned(),
+            link: "https://example.com/a (b)".to_owned(),
+            original_link: None,
+            pub_date: DateTime::parse_from_rfc3339("2024-05-01T10:00:00Z").ok(),
+            source_name: "Blog #1".to_owned(),
+            source_url: "https://example.com".to_owned(),
+            feed_url: "https://example.com/feed".to_owned(),
+            description: None,
+            content: None,
+            author: None,
+            categories: Vec::new(),
+            enclosure: None,
+            image: None,
+            source_language: None,
+            copyright: None,
+        };
+        assert_eq!(
+            render_markdown(&item, now, false, Locale::En),
+            "- [\\[RFC\\] \\*Async\\* traits\\_v2](https://example.com/a%20%28b%29) — Blog \\#1 (2 days ago)\n"
+        );
+        assert!(render_markdown(&item, now, true, Locale::En).ends_with("%29) (2 days ago)\n"));
+    }
+
+    #[test]
+    fn render_csv_quotes_fields() {
+        let mut item = FeedItem {
+            guid: None,
+            title: "Say \"hi\", then\nbye".to_owned(),
+            link: "https://example.com/a?b=1".to_owned(),
+            original_link: None,
+            pub_date: DateTime::parse_from_rfc3339("2024-05-01T10:00:00+02:00").ok(),
+            source_name: "Blog, Inc".to_owned(),
+            source_url: "https://example.com".to_owned(),
+            feed_url: "https://example.com/feed".to_owned(),
+            description: None,
+            content: None,
+            author: None,
+            categories: Vec::new(),
+            enclosure: None,
+            image: None,
+            source_language: None,
+            copyright: None,
+        };
+        assert_eq!(
+            render_csv(&item, false),
+            "2024-05-01T10:00:00+02:00,\"Blog, Inc\",\"Say \"\"hi\"\", then\nbye\",https://example.com/a?b=1,false\r\n"
+        );
+        item.pub_date = None;
+        item.title = "Plain".to_owned();
+        assert_eq!(
+            render_csv(&item, true),
+            ",\"Blog, Inc\",Plain,https://example.com/a?b=1,true\r\n"
+        );
+    }
+
+    #[test]
+    fn clean_title_markup() {
+        assert_eq!(clean_title("Foo &amp; Bar"), "Foo & Bar");
+        assert_eq!(clean_title("It&#8217;s &#x263A; time"), "It’s ☺ time");
+        assert_eq!(clean_title("<b><i>Nested</i> tags</b>"), "Nested tags");
+        assert_eq!(clean_title("Vec<T> in <em>Rust</em>"), "Vec<T> in Rust");
+        assert_eq!(clean_title("AT&T  &unknown; rocks"), "AT&T &unknown; rocks");
+        assert_eq!(clean_title("Already  clean title"), "Already  clean title");
+    }
+
+    #[test]
+    fn fuzzy_contains_tolerates_typos() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert!(fuzzy_contains("Kubernetes 1.30 released", "kubernetse"));
+        assert!(fuzzy_contains(
+            "New in Rust: async closures",
+            "async clsures"
+        ));
+        assert!(fuzzy_contains("Rustup 1.27", "RUSTUP"));
+        // short words need to match exactly
+        assert!(!fuzzy_contains("Go 1.22", "Ga"));
+        assert!(!fuzzy_contains("Kubernetes 1.30 released", "kubectl"));
+    }
+
+    #[test]
+    fn normalize_url_ignores_cosmetics() {
+        assert_eq!(normalize_url("http://X.com/feed"), "x.com/feed");
+        assert_eq!(normalize_url("https://x.com/feed/#top"), "x.com/feed");
+        assert_ne!(normalize_url("https://x.com/feed?page=2"), "x.com/feed");
+    }
+
+    #[test]
+    fn strip_tracking_params_keeps_others() {
+        assert_eq!(
+            strip_tracking_params("https://x.com/post?utm_source=rss&id=3&UTM_Medium=feed#top"),
+            "https://x.com/post?id=3#top"
+        );
+        assert_eq!(
+            strip_tracking_params("https://x.com/post?fbclid=abc"),
+            "https://x.com/post"
+        );
+        assert_eq!(
+            strip_tracking_params("https://x.com/p?a=1"),
+            "https://x.com/p?a=1"
+        );
+        assert!(is_feedproxy_link(
+            "http://feedproxy.google.com/~r/blog/~3/abc/"
+        ));
+        assert!(!is_feedproxy_link("https://x.com/feedproxy.google.com"));
+    }
+
+    #[test]
+    fn normalize_shouting_titles() {
+        assert_eq!(
+            normalize_title("KUBERNETES NEWS TODAY"),
+            "Kubernetes news today"
+        );
+        assert_eq!(
+            normalize_title("NASA SHIPS 3 HTML5 PROBES: WHAT I'M WATCHING ON BBC"),
+            "NASA ships 3 HTML5 probes: What I'm watching on BBC"
+        );
+        assert_eq!(normalize_title("\"BREAKING\" STORY"), "\"Breaking\" story");
+        assert_eq!(normalize_title("Mixed CASE title"), "Mixed CASE title");
+        assert_eq!(normalize_title("BREAKING"), "BREAKING");
+    }
+
+    #[test]
+    fn enclosure_media_detection() {
+        let audio = Enclosure::new(
+            " https://x.org/ep1.mp3 ",
+            Some("audio/mpeg"),
+            Some("12345678"),
+        );
+        let audio = audio.unwrap();
+        assert!(audio.is_media());
+        assert_eq!(
+            audio.to_string(),
+            "https://x.org/ep1.mp3 (audio/mpeg, 12.3 MB)"
+        );
+        let untyped = Enclosure::new("https://x.org/ep2.M4A?token=1", Some(""), Some("n/a"));
+        assert!(untyped.unwrap().is_media());
+        let image = Enclosure::new("https://x.org/cover.jpg", Some("image/jpeg"), None);
+        assert!(!image.unwrap().is_media());
+        assert_eq!(Enclosure::new("", None, None), None);
+    }
+
+    #[test]
+    fn author_name_forms() {
+        assert_eq!(
+            author_name("jane@example.com (Jane Doe)").as_deref(),
+            Some("Jane Doe")
+        );
+        assert_eq!(author_name(" Jane Doe ").as_deref(), Some("Jane Doe"));
+        assert_eq!(
+            author_name("jane@example.com").as_deref(),
+            Some("jane@example.com")
+        );
+        assert_eq!(
+            author_name("Team (London)").as_deref(),
+            Some("Team (London)")
+        );
+        assert_eq!(author_name("  "), None);
+    }
+}
@@ -1,157 +1,473 @@
-use chrono::{self, DateTime, FixedOffset, TimeDelta};
-use colored::*;
-use quick_xml::de::from_str;
-use rss::{Channel, Item};
-use serde;
-use std::{error::Error, str::FromStr};
-
-#[derive(Debug, serde::Deserialize)]
-pub struct Opml {
-    #[serde(rename = "@version")]
-    pub version: String,
-    pub head: Head,
-    pub body: BodyList,
-}
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use std::{collections::HashSet, path::Path};
 
-#[derive(Debug, serde::Deserialize)]
-pub struct Head {
-    pub title: String,
+pub mod bookmarks;
+pub mod config;
+pub mod error;
+mod feed;
+mod html;
+pub mod inflate;
+mod item;
+pub mod json;
+mod opml;
+pub mod regex;
+mod time;
+pub mod tz;
+
+pub use error::Error;
+pub use feed::*;
+pub use html::*;
+pub use item::*;
+pub use opml::*;
+pub use time::*;
+
+/// Items already shown to the user, each with the time it was first shown.
+/// Stored one per line as `<RFC3339 time>\t<feed url>\t<guid>`, so feeds using the
+/// same guids, e.g. sequential numbers, don't mark each other's items seen.
+/// `<RFC3339 time>\t<guid>` lines of older versions match items of any feed, and
+/// plain `<guid>` lines have no time either and are never pruned.
+#[derive(Debug, Default)]
+pub struct SeenStore {
+    /// Entries by key, `<feed url>\t<guid>` or a plain guid of older versions
+    entries: Vec<(String, Option<DateTime<FixedOffset>>)>,
+    /// Keys of `entries` for constant time lookups
+    index: HashSet<String>,
 }
 
-#[derive(Debug, serde::Deserialize)]
-pub struct BodyList {
-    pub outline: Vec<Outline>,
+/// Key of `item` in [`SeenStore`]
+fn seen_key(item: &FeedItem) -> String {
+    format!("{}\t{}", item.feed_url, item.get_id())
 }
 
-#[derive(Debug, serde::Deserialize)]
-pub struct Outline {
-    #[serde(rename = "@text")]
-    pub text: Option<String>,
-    #[serde(rename = "@title")]
-    pub title: String,
-    #[serde(rename = "@type")]
-    pub outline_type: String,
-    #[serde(rename = "@xmlUrl")]
-    pub xml_url: String,
+impl SeenStore {
+    /// Reads the store from file. Missing file is an empty store
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Ok(Self::parse(&content)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let mut store = SeenStore::default();
+        for line in content.lines().filter(|line| !line.is_empty()) {
+            let (guid, time) = line
+                .split_once('\t')
+                .and_then(|(time, guid)| {
+                    let time = DateTime::parse_from_rfc3339(time).ok()?;
+                    Some((guid, Some(time)))
+                })
+                .unwrap_or((line, None));
+            if store.index.insert(guid.to_owned()) {
+                store.entries.push((guid.to_owned(), time));
+            }
+        }
+        store
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.serialize())
+    }
+
+    pub fn serialize(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(guid, time)| match time {
+                Some(time) => format!(
+                    "{}\t{}\n",
+                    time.to_rfc3339_opts(SecondsFormat::Secs, false),
+                    guid
+                ),
+                None => format!("{}\n", guid),
+            })
+            .collect()
+    }
+
+    /// Checks if entry of `key` is stored, see [`SeenStore::contains_item`] for items
+    pub fn contains(&self, key: &str) -> bool {
+        self.index.contains(key)
+    }
+
+    /// Stores entry of `key`. Already stored entries keep their original time
+    pub fn insert(&mut self, key: String, seen_at: DateTime<FixedOffset>) {
+        if self.index.insert(key.clone()) {
+            self.entries.push((key, Some(seen_at)));
+        }
+    }
+
+    /// Checks if item was seen in its feed, or with its guid in any feed by older versions
+    pub fn contains_item(&self, item: &FeedItem) -> bool {
+        self.contains(&seen_key(item)) || self.contains(&item.get_id())
+    }
+
+    /// Marks item as seen. Already seen items keep their original time
+    pub fn insert_item(&mut self, item: &FeedItem, seen_at: DateTime<FixedOffset>) {
+        if !self.contains_item(item) {
+            self.insert(seen_key(item), seen_at);
+        }
+    }
+
+    /// Marks item as not seen, returns false if it wasn't seen
+    pub fn remove_item(&mut self, item: &FeedItem) -> bool {
+        self.remove_from_feed(&item.feed_url, &item.get_id())
+    }
+
+    /// Marks item with `guid` of the feed at `feed_url` as not seen, returns false
+    /// if it wasn't seen
+    pub fn remove_from_feed(&mut self, feed_url: &str, guid: &str) -> bool {
+        let key = format!("{}\t{}", feed_url, guid);
+        self.remove_where(|seen| *seen == key || seen == guid)
+    }
+
+    /// Marks items with `guid` as not seen in every feed, returns false if none was seen
+    pub fn remove(&mut self, guid: &str) -> bool {
+        self.remove_where(|key| {
+            let seen = key.split_once('\t').map_or(key, |(_, seen)| seen);
+            seen == guid
+        })
+    }
+
+    fn remove_where(&mut self, mut matches: impl FnMut(&str) -> bool) -> bool {
+        let count = self.entries.len();
+        self.entries.retain(|(key, _)| !matches(key));
+        if self.entries.len() == count {
+            return false;
+        }
+        self.index = self.entries.iter().map(|(key, _)| key.clone()).collect();
+        true
+    }
+
+    /// Replaces pseudo guids older versions gave `items` without guids with their
+    /// current keys, keeping the time they were seen, so they aren't shown as new again
+    pub fn adopt_legacy_ids(&mut self, items: &[FeedItem]) {
+        for item in items {
+            let Some(legacy) = item.legacy_id() else {
+                continue;
+            };
+            if item.get_id() == legacy || self.contains_item(item) || !self.index.remove(&legacy) {
+                continue;
+            }
+            let key = seen_key(item);
+            if let Some(entry) = self.entries.iter_mut().find(|(seen, _)| *seen == legacy) {
+                entry.0 = key.clone();
+            }
+            self.index.insert(key);
+        }
+    }
+
+    /// Drops entries first seen before `cutoff`
+    pub fn prune(&mut self, cutoff: DateTime<FixedOffset>) {
+        self.entries
+            .retain(|(_, time)| time.is_none_or(|time| time >= cutoff));
+        self.index = self.entries.iter().map(|(key, _)| key.clone()).collect();
+    }
 }
 
-pub fn read_opml(file: &str) -> Result<Opml, Box<dyn Error>> {
-    let content = std::fs::read_to_string(file)?;
-    let doc: Opml = from_str(&content)?;
-    Ok(doc)
+/// Newest item of a feed when it was last read by `show-sources --stats`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeedStats {
+    /// Date of the newest item, `None` when the feed had no dated items
+    pub newest: Option<DateTime<FixedOffset>>,
+    pub checked: DateTime<FixedOffset>,
 }
 
-pub fn get_rss_outlines(opml: &Opml) -> Vec<&Outline> {
-    opml.body
-        .outline
-        .iter()
-        .filter(|outline| outline.outline_type == "rss")
-        .collect()
+/// [`FeedStats`] by feed URL. Stored one per line as
+/// `<RFC3339 checked>\t<RFC3339 newest or ->\t<url>`, sorted by URL
+#[derive(Debug, Default)]
+pub struct FeedStatsStore {
+    entries: std::collections::BTreeMap<String, FeedStats>,
 }
 
-pub async fn read_feed(url: &str) -> Result<Channel, Box<dyn Error>> {
-    let client = reqwest::Client::new();
-    let content = client.get(url).send().await?.bytes().await?;
-    let channel = Channel::read_from(&content[..])?;
-    Ok(channel)
+impl FeedStatsStore {
+    /// Reads the store from file. Missing file is an empty store
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Ok(Self::parse(&content)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Parses lines written by [`FeedStatsStore::serialize`], skipping malformed ones
+    pub fn parse(content: &str) -> Self {
+        let mut store = FeedStatsStore::default();
+        for line in content.lines() {
+            let mut fields = line.splitn(3, '\t');
+            let (Some(checked), Some(newest), Some(url)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            let Ok(checked) = DateTime::parse_from_rfc3339(checked) else {
+                continue;
+            };
+            let newest = DateTime::parse_from_rfc3339(newest).ok();
+            store
+                .entries
+                .insert(url.to_owned(), FeedStats { newest, checked });
+        }
+        store
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, self.serialize())
+    }
+
+    pub fn serialize(&self) -> String {
+        let time = |time: DateTime<FixedOffset>| time.to_rfc3339_opts(SecondsFormat::Secs, false);
+        self.entries
+            .iter()
+            .map(|(url, stats)| {
+                let newest = stats.newest.map_or("-".to_owned(), time);
+                format!("{}\t{}\t{}\n", time(stats.checked), newest, url)
+            })
+            .collect()
+    }
+
+    pub fn get(&self, url: &str) -> Option<&FeedStats> {
+        self.entries.get(url)
+    }
+
+    pub fn insert(&mut self, url: String, stats: FeedStats) {
+        self.entries.insert(url, stats);
+    }
 }
 
-pub struct FeedItem {
-    guid: Option<String>,
-    pub title: String,
+/// Item of the last listing, so it can be acted on by its number
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListedItem {
+    pub id: String,
     pub link: String,
-    pub pub_date: DateTime<FixedOffset>,
+    pub title: String,
     pub source_name: String,
-    pub source_url: String,
+    pub pub_date: Option<DateTime<FixedOffset>>,
+    /// URL of the feed of the item, `None` in listings of older versions
+    pub feed_url: Option<String>,
+}
+
+impl ListedItem {
+    /// Checks if `item` is the listed one by its id, within its feed when known
+    pub fn is(&self, item: &FeedItem) -> bool {
+        item.get_id() == self.id
+            && self
+                .feed_url
+                .as_ref()
+                .is_none_or(|feed_url| *feed_url == item.feed_url)
+    }
 }
 
-impl FeedItem {
-    pub fn make(item: &Item, source_name: &str, source_link: &str) -> Result<Self, String> {
-        let guid = item.guid().map(|x| x.value.clone());
-        let title = item
-            .title()
-            .map(|s| s.to_owned())
-            .ok_or("Title not found".to_owned())?;
-        let link = item
-            .link()
-            .map(|s| s.to_owned())
-            .ok_or("Link not found".to_owned())?;
-        let raw_pub_date = item.pub_date().ok_or("Pub date not found")?;
-        let pub_date = DateTime::parse_from_rfc2822(raw_pub_date)
-            .or(DateTime::from_str(raw_pub_date))
-            .map_err(|err| err.to_string())?;
-        Ok(FeedItem {
-            guid,
-            title,
-            link,
-            pub_date,
-            source_name: source_name.to_owned(),
-            source_url: source_link.to_owned(),
+/// Last shown items, so they can be opened by their number.
+/// Stored as `<id>\t<link>\t<title>\t<source>\t<RFC3339 date>\t<feed url>` lines, the first line
+/// being item 1
+pub fn save_listing<'a>(
+    path: &Path,
+    items: impl IntoIterator<Item = &'a FeedItem>,
+) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let field = |value: &str| value.replace(['\t', '\n'], " ");
+    let content: String = items
+        .into_iter()
+        .map(|item| {
+            format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\n",
+                field(&item.get_id()),
+                item.link,
+                field(&item.title),
+                field(&item.source_name),
+                item.pub_date
+                    .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, false))
+                    .unwrap_or_default(),
+                item.feed_url
+            )
         })
+        .collect();
+    std::fs::write(path, content)
+}
+
+/// Reads items written by `save_listing`. Listings of older versions have
+/// `<id>\t<link>` lines only, their items have no title or source
+pub fn load_listing(path: &Path) -> std::io::Result<Vec<ListedItem>> {
+    let content = std::fs::read_to_string(path)?;
+    Ok(content.lines().filter_map(parse_listed_item).collect())
+}
+
+fn parse_listed_item(line: &str) -> Option<ListedItem> {
+    let mut fields = line.split('\t');
+    let (id, link) = (fields.next()?, fields.next()?);
+    let mut next = || fields.next().unwrap_or_default().to_owned();
+    Some(ListedItem {
+        id: id.to_owned(),
+        link: link.to_owned(),
+        title: next(),
+        source_name: next(),
+        pub_date: DateTime::parse_from_rfc3339(&next()).ok(),
+        feed_url: Some(next()).filter(|feed_url| !feed_url.is_empty()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    #[test]
+    fn feed_stats_round_trip() {
+        let date = |text| DateTime::parse_from_rfc3339(text).unwrap();
+        let mut store = FeedStatsStore::parse("garbage\n");
+        store.insert(
+            "https://b.example/feed".to_owned(),
+            FeedStats {
+                newest: Some(date("2024-05-01T10:00:00+02:00")),
+                checked: date("2024-05-02T08:00:00+00:00"),
+            },
+        );
+        store.insert(
+            "https://a.example/feed".to_owned(),
+            FeedStats {
+                newest: None,
+                checked: date("2024-05-02T08:00:00+00:00"),
+            },
+        );
+        let serialized = store.serialize();
+        assert_eq!(
+            serialized,
+            "2024-05-02T08:00:00+00:00\t-\thttps://a.example/feed\n\
+             2024-05-02T08:00:00+00:00\t2024-05-01T10:00:00+02:00\thttps://b.example/feed\n"
+        );
+        let parsed = FeedStatsStore::parse(&serialized);
+        assert_eq!(
+            parsed.get("https://b.example/feed"),
+            store.get("https://b.example/feed")
+        );
+        assert_eq!(parsed.get("https://a.example/feed").unwrap().newest, None);
     }
 
-    /// Returns guid of the item. If not found, then constructs pseudo guid from title and link
-    pub fn get_id(&self) -> String {
-        self.guid
-            .as_ref()
-            .map(|s| s.clone())
-            .unwrap_or_else(|| format!("{}-{}", self.title, self.link))
+    #[test]
+    fn parse_listing_lines() {
+        let item = parse_listed_item(
+            "urn:1\thttps://example.com/1\tHello\tExample\t2024-05-01T10:00:00+02:00",
+        )
+        .unwrap();
+        assert_eq!(item.title, "Hello");
+        assert_eq!(item.source_name, "Example");
+        assert_eq!(
+            item.pub_date,
+            DateTime::parse_from_rfc3339("2024-05-01T10:00:00+02:00").ok()
+        );
+        // listings of older versions
+        let item = parse_listed_item("urn:1\thttps://example.com/1").unwrap();
+        assert_eq!(item.link, "https://example.com/1");
+        assert_eq!((item.title.as_str(), item.pub_date), ("", None));
+        assert_eq!(item.feed_url, None);
+        assert_eq!(parse_listed_item("urn:1"), None);
     }
 
-    pub fn show(&self, now: DateTime<FixedOffset>, already_seen: bool) {
-        let title = self.title.as_str();
-        let link = self.link.as_str();
-        let source = self.source_name.as_str();
-        let dt_ago = date_diff(now - self.pub_date);
-        if already_seen {
-            println!("{}: {} ({}) {}", source, title.hidden(), dt_ago.dimmed(), link);
-        } else {
-            println!("{} (*new*): {} ({}) {}", source, title.bold(), dt_ago.dimmed(), link);
-        }
+    #[test]
+    fn seen_store_adopts_legacy_ids() {
+        let feed = Feed::parse(
+            br#"<rss version="2.0"><channel><title>T</title><link>https://example.com</link>
+                <item><title>Old</title><link>https://example.com/old</link></item>
+                <item><title>Tagged</title><link>https://example.com/t</link><guid>t</guid></item>
+            </channel></rss>"#,
+        )
+        .unwrap();
+        let items = read_feed_items(&feed, None, "https://example.com/feed").items;
+        let mut store = SeenStore::parse(
+            "2024-01-01T00:00:00+00:00\tOld-https://example.com/old\n\
+             2024-01-02T00:00:00+00:00\tt\n",
+        );
+        store.adopt_legacy_ids(&items);
+        assert!(store.contains_item(&items[0]));
+        assert!(!store.contains("Old-https://example.com/old"));
+        assert_eq!(
+            store.serialize(),
+            "2024-01-01T00:00:00+00:00\thttps://example.com/feed\texample.com/old\n\
+             2024-01-02T00:00:00+00:00\tt\n"
+        );
     }
-}
 
-pub fn read_feed_items(channel: &Channel) -> Vec<FeedItem> {
-    let converted = channel
-        .items()
-        .iter()
-        .map(|item| FeedItem::make(item, channel.title(), channel.link()));
+    #[test]
+    fn seen_store_namespaces_guids_by_feed() {
+        let read = |site: &str, feed_url: &str| {
+            let feed = Feed::parse(
+                format!(
+                    r#"<rss version="2.0"><channel><title>T</title><link>{}</link>
+                        <item><title>A</title><link>{}/a</link><guid>123</guid></item>
+                    </channel></rss>"#,
+                    site, site
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+            read_feed_items(&feed, None, feed_url).items.remove(0)
+        };
+        let first = read("https://a.example", "https://a.example/feed");
+        let second = read("https://b.example", "https://b.example/feed");
+        // posts and comments of one site share its link
+        let comments = read("https://a.example", "https://a.example/comments");
+        let now = DateTime::parse_from_rfc3339("2024-06-01T00:00:00Z").unwrap();
+        let mut store = SeenStore::default();
+        store.insert_item(&first, now);
+        assert!(store.contains_item(&first));
+        assert!(!store.contains_item(&second) && !store.contains_item(&comments));
+        let store = SeenStore::parse(&store.serialize());
+        assert!(store.contains_item(&first));
+        assert!(!store.contains_item(&second) && !store.contains_item(&comments));
 
-    let failed = converted.clone().filter_map(Result::err);
-    let successful = converted.filter_map(Result::ok);
+        let mut items = vec![
+            read("https://a.example", "https://a.example/feed"),
+            read("https://b.example", "https://b.example/feed"),
+            read("https://a.example", "https://a.example/feed"),
+        ];
+        dedup_items(&mut items);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].feed_url, "https://b.example/feed");
 
-    failed.for_each(|err| eprintln!("{} Invalid RSS item in feed: {}", "[WARNING]".red(), err));
-    successful.collect()
-}
+        // listed items are told apart by their feeds too
+        let path = std::env::temp_dir().join(format!("fdr-listing-test-{}", std::process::id()));
+        save_listing(&path, [&first, &second]).unwrap();
+        let listing = load_listing(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(listing[1].is(&second) && !listing[1].is(&first));
+        let mut store = SeenStore::default();
+        store.insert_item(&first, now);
+        store.insert_item(&second, now);
+        let listed = &listing[0];
+        assert!(store.remove_from_feed(listed.feed_url.as_deref().unwrap(), &listed.id));
+        assert!(!store.contains_item(&first) && store.contains_item(&second));
+
+        // guids stored by older versions match items of any feed
+        let mut store = SeenStore::parse("2024-01-01T00:00:00+00:00\t123\n");
+        assert!(store.contains_item(&first) && store.contains_item(&second));
+        store.insert_item(&first, now);
+        store.insert_item(&second, now);
+        assert_eq!(store.serialize(), "2024-01-01T00:00:00+00:00\t123\n");
+        assert!(store.remove("123"));
+        assert!(!store.contains_item(&second));
+    }
 
-/// Converts time delta to human friendly string
-/// e.g. "just now", "1 day ago", etc
-pub fn date_diff(delta: TimeDelta) -> String {
-    if delta.num_days() == 365 {
-        "year ago".to_owned()
-    } else if delta.num_days() > 365 {
-        format!("{} years ago", delta.num_days() % 365)
-    } else if delta.num_weeks() == 4 {
-        "month ago".to_owned()
-    } else if delta.num_weeks() > 4 {
-        format!("{} months ago", delta.num_days() % 30)
-    } else if delta.num_weeks() == 1 {
-        "week ago".to_owned()
-    } else if delta.num_weeks() > 1 {
-        format!("{} weeks ago", delta.num_weeks())
-    } else if delta.num_days() == 1 {
-        "day ago".to_owned()
-    } else if delta.num_days() > 1 {
-        format!("{} days ago", delta.num_days())
-    } else if delta.num_hours() == 1 {
-        "hour ago".to_owned()
-    } else if delta.num_hours() > 1 {
-        format!("{} hours ago", delta.num_hours())
-    } else if delta.num_minutes() == 1 {
-        "minute ago".to_owned()
-    } else if delta.num_minutes() > 1 {
-        format!("{} minutes ago", delta.num_minutes())
-    } else {
-        "just now".to_owned()
+    #[test]
+    fn seen_store_prunes_dated_entries_only() {
+        let now = DateTime::parse_from_rfc3339("2024-06-01T00:00:00Z").unwrap();
+        let mut store =
+            SeenStore::parse("legacy-guid\nlegacy-guid\n2024-01-01T00:00:00+00:00\told-guid");
+        store.insert("new-guid".to_owned(), now);
+        store.prune(now - TimeDelta::days(90));
+        assert!(store.contains("legacy-guid"));
+        assert!(!store.contains("old-guid"));
+        assert!(store.contains("new-guid"));
+        assert_eq!(
+            store.serialize(),
+            "legacy-guid\n2024-06-01T00:00:00+00:00\tnew-guid\n"
+        );
     }
 }
@@ -1,94 +1,2940 @@
-use chrono::Local;
-use clap::{Parser, Subcommand, ValueEnum};
-use fdr;
-use tokio;
+use chrono::{Local, Utc};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use colored::*;
+use futures_util::{stream, StreamExt};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io::{IsTerminal, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
-#[derive(ValueEnum, Debug, Clone)]
-enum SortMode {
+mod notify;
+#[cfg(unix)]
+mod tui;
+
+/// Key items are ordered by, see [`parse_sort_spec`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortKey {
+    /// Order of feeds in OPML and of items in feeds
     Original,
-    Desc,
-    Asc,
+    /// Newest first
+    DateDesc,
+    /// Oldest first
+    DateAsc,
+    /// By source name
+    Source,
+    /// By title
+    Title,
+}
+
+/// Keys of `--sort`, later ones order items the earlier ones find equal
+#[derive(Debug, Clone, PartialEq)]
+struct SortSpec(Vec<SortKey>);
+
+/// Parses comma separated sort keys, e.g. `source,date-desc`. `desc` and `asc`
+/// are short for the date keys, and `source` alone sorts newest first within a source
+fn parse_sort_spec(value: &str) -> Result<SortSpec, String> {
+    let keys = value
+        .split(',')
+        .map(|key| match key.trim().to_lowercase().as_str() {
+            "original" => Ok(SortKey::Original),
+            "desc" | "date-desc" => Ok(SortKey::DateDesc),
+            "asc" | "date-asc" => Ok(SortKey::DateAsc),
+            "source" => Ok(SortKey::Source),
+            "title" => Ok(SortKey::Title),
+            other => Err(format!(
+                "unknown sort key '{}', expected original, desc, asc, date-desc, date-asc, \
+                 source or title",
+                other
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if keys == [SortKey::Source] {
+        return Ok(SortSpec(vec![SortKey::Source, SortKey::DateDesc]));
+    }
+    Ok(SortSpec(keys))
+}
+
+/// Sorts items by `spec`. Items without date go last by the date keys. Remaining ties
+/// are broken by id, so the order is the same between runs, unless `original` is
+/// one of the keys
+fn sort_items(items: &mut [fdr::FeedItem], spec: &SortSpec) {
+    if spec.0.iter().all(|key| *key == SortKey::Original) {
+        return;
+    }
+    let compare = |a: &fdr::FeedItem, b: &fdr::FeedItem, key: SortKey| {
+        let undated_last = || a.pub_date.is_none().cmp(&b.pub_date.is_none());
+        match key {
+            SortKey::Original => std::cmp::Ordering::Equal,
+            SortKey::DateDesc => undated_last().then_with(|| b.pub_date.cmp(&a.pub_date)),
+            SortKey::DateAsc => undated_last().then_with(|| a.pub_date.cmp(&b.pub_date)),
+            SortKey::Source => a
+                .source_name
+                .to_lowercase()
+                .cmp(&b.source_name.to_lowercase()),
+            SortKey::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+        }
+    };
+    let by_id = !spec.0.contains(&SortKey::Original);
+    // sort_by is stable, so `original` keeps feed order among equal items
+    items.sort_by(|a, b| {
+        let ordering = spec
+            .0
+            .iter()
+            .fold(std::cmp::Ordering::Equal, |ordering, key| {
+                ordering.then_with(|| compare(a, b, *key))
+            });
+        if by_id {
+            ordering.then_with(|| a.get_id().cmp(&b.get_id()))
+        } else {
+            ordering
+        }
+    });
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum DateFormat {
+    /// `3 days ago`
+    Relative,
+    /// Date formatted with `--date-pattern`
+    Absolute,
+    /// Both, e.g. `2024-01-15 10:00, 3 days ago`
+    Both,
+    /// `today, 14:05` and `yesterday, 14:05` for recent items, relative otherwise
+    Calendar,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Text,
+    Json,
+    Ndjson,
+    Csv,
+    Markdown,
+}
+
+impl OutputFormat {
+    fn is_json(self) -> bool {
+        matches!(self, OutputFormat::Json | OutputFormat::Ndjson)
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum PathsFormat {
+    Text,
+    Json,
 }
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
-struct CLI {
+struct Cli {
     #[command(subcommand)]
     operation: Operation,
 
-    #[arg(short, long, action = clap::ArgAction::Count)]
+    /// Print more details to stderr, repeat for even more
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
     verbose: u8,
+
+    /// Disable colored output
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Time zone of shown dates, e.g. Europe/Berlin, UTC or +05:30. Defaults to local time
+    #[arg(long, global = true, value_name = "TZ", allow_hyphen_values = true, value_parser = fdr::tz::TimeZone::lookup)]
+    timezone: Option<fdr::tz::TimeZone>,
+
+    /// Language of relative dates of items: en, es, de or fr. Defaults to that of `LANG`
+    #[arg(long, global = true, value_name = "LANG", value_parser = fdr::Locale::lookup)]
+    locale: Option<fdr::Locale>,
 }
 
 #[derive(Debug, Subcommand, Clone)]
+#[allow(clippy::large_enum_variant)]
 enum Operation {
-    ShowNews {
+    ShowNews(ShowNewsArgs),
+    /// Like `show-news`, listing only items with audio or video, e.g. podcast episodes
+    Episodes(ShowNewsArgs),
+    ShowSources {
         opml: String,
-        #[arg(short, long, action = clap::ArgAction::SetTrue)]
-        all: bool,
-        #[arg(value_enum, default_value = "original")]
-        sort: SortMode,
+        /// Show only feeds in this category, e.g. `News/World`
+        #[arg(long, value_name = "NAME")]
+        category: Option<String>,
+        /// Fetch feeds and show their own title, link, description and item count
+        #[arg(long)]
+        live: bool,
+        /// Fetch feeds and show how old their newest item is and how many items
+        /// they added since the last run with `--stats`
+        #[arg(long)]
+        stats: bool,
+        #[command(flatten)]
+        stale: StaleArgs,
+        #[command(flatten)]
+        fetch: FetchArgs,
     },
-    ShowSources {
+    /// Fetch every feed and report which ones are broken
+    Check {
+        opml: String,
+        /// Update URLs of feeds that moved permanently in the OPML file
+        #[arg(long)]
+        fix_redirects: bool,
+        #[command(flatten)]
+        stale: StaleArgs,
+        #[command(flatten)]
+        fetch: FetchArgs,
+    },
+    /// Report feeds subscribed more than once, e.g. in different categories
+    DedupCheck {
         opml: String,
+        /// Remove the duplicates from the OPML file, keeping the first of each feed
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Find feeds of a website, e.g. to subscribe to them
+    Discover {
+        /// Address of the site or of any of its pages
+        url: String,
+        /// Append the found feeds to this OPML file, created if missing
+        #[arg(long, value_name = "OPML")]
+        add_to: Option<String>,
+        #[command(flatten)]
+        fetch: FetchArgs,
+    },
+    /// Browse items of all feeds in a full screen list, marking them read as you go
+    Tui {
+        #[arg(required = true)]
+        opml: Vec<String>,
+        /// Show only items of feeds in this category, e.g. `News/World`
+        #[arg(long, value_name = "NAME")]
+        category: Option<String>,
+        /// File with already seen items, see `show-news --seen-file`
+        #[arg(long, value_name = "PATH")]
+        seen_file: Option<PathBuf>,
+        #[command(flatten)]
+        fetch: FetchArgs,
+    },
+    /// Open an item of the last `show-news` listing in the browser
+    Open {
+        /// OPML files the listing was made from
+        #[arg(required = true)]
+        opml: Vec<String>,
+        /// Number of the item, as printed with `show-news --numbered`
+        index: NonZeroUsize,
+    },
+    /// Show full text of an item of the last `show-news` listing in the pager
+    Read {
+        /// OPML files the listing was made from
+        #[arg(required = true)]
+        opml: Vec<String>,
+        /// Number of the item, as printed with `show-news --numbered`
+        index: NonZeroUsize,
+        #[command(flatten)]
+        fetch: FetchArgs,
+    },
+    /// Save an item of the last `show-news` listing to read later
+    Bookmark {
+        /// OPML files the listing was made from
+        #[arg(required = true)]
+        opml: Vec<String>,
+        /// Number of the item, as printed with `show-news --numbered`
+        index: NonZeroUsize,
+    },
+    /// List saved items
+    Bookmarks {
+        /// Remove the bookmark with this id instead
+        #[arg(long, value_name = "ID")]
+        remove: Option<String>,
+    },
+    /// Mark all current items as seen without showing them
+    MarkRead {
+        #[arg(required = true)]
+        opml: Vec<String>,
+        /// Mark only items of feeds in this category, e.g. `News/World`
+        #[arg(long, value_name = "NAME")]
+        category: Option<String>,
+        /// File with already seen items, see `show-news --seen-file`
+        #[arg(long, value_name = "PATH")]
+        seen_file: Option<PathBuf>,
+        #[command(flatten)]
+        fetch: FetchArgs,
+    },
+    /// Mark an item as not seen, so `show-news` reports it as new again
+    MarkUnread {
+        #[arg(required = true)]
+        opml: Vec<String>,
+        /// Id of the item as printed by `--format json`, or its link
+        item: String,
+        /// File with already seen items, see `show-news --seen-file`
+        #[arg(long, value_name = "PATH")]
+        seen_file: Option<PathBuf>,
+    },
+    /// Write OPML back to a file, optionally without broken feeds
+    Export {
+        input: String,
+        output: String,
+        /// Remove feeds that `check` reports as failed
+        #[arg(long)]
+        drop_failing: bool,
+        #[command(flatten)]
+        fetch: FetchArgs,
+    },
+    /// Combine OPML files into one, merging categories of the same name and
+    /// dropping feeds subscribed more than once
+    Merge {
+        /// OPML files or URLs to merge, titles of feeds in several are taken from the first
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        /// Where to write the merged OPML, `-` for stdout
+        #[arg(short, long, value_name = "FILE", default_value = "-")]
+        output: String,
+    },
+    /// Print where settings and state are kept, and the settings in effect
+    /// after merging the config file and the command line
+    Paths {
+        /// OPML files to show the seen file and other state of
+        opml: Vec<String>,
+        /// File with already seen items, see `show-news --seen-file`
+        #[arg(long, value_name = "PATH")]
+        seen_file: Option<PathBuf>,
+        #[arg(long, value_enum, default_value = "text")]
+        format: PathsFormat,
+        #[command(flatten)]
+        fetch: FetchArgs,
     },
 }
 
-async fn show_news(
-    opml: &str,
+/// Options of fetching feeds shared by subcommands
+#[derive(Debug, Args, Clone)]
+struct FetchArgs {
+    /// Maximum number of feeds fetched at the same time
+    #[arg(long, default_value = "8")]
+    concurrency: NonZeroUsize,
+    /// Give up on a feed after this long, e.g. 30s
+    #[arg(long, value_name = "DURATION", default_value = "15s", value_parser = fdr::parse_duration)]
+    timeout: chrono::TimeDelta,
+    /// Give up on a feed after following this many redirects
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    max_redirects: usize,
+    /// Repeat requests failed with timeouts, connection errors or 5xx this many times
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    retries: u32,
+    /// Maximum number of requests sent to a single host at the same time
+    #[arg(long, value_name = "N", default_value = "2")]
+    per_host_concurrency: NonZeroUsize,
+    /// Wait at least this long between requests to a single host, e.g. 1s
+    #[arg(long, value_name = "DURATION", default_value = "500ms", value_parser = fdr::parse_duration)]
+    per_host_interval: chrono::TimeDelta,
+}
+
+impl FetchArgs {
+    fn build_client(&self) -> Result<fdr::FeedClient, Box<dyn Error>> {
+        let options = fdr::ClientOptions {
+            timeout: self.timeout.to_std()?,
+            max_redirects: self.max_redirects,
+            retries: self.retries,
+            per_host_concurrency: self.per_host_concurrency.get(),
+            per_host_interval: self.per_host_interval.to_std()?,
+        };
+        Ok(fdr::build_client(&options)?)
+    }
+}
+
+/// When `check` and `show-sources` flag feeds as possibly stale
+#[derive(Debug, Args, Clone)]
+struct StaleArgs {
+    /// Flag feeds whose newest item is older than N times the `<ttl>` they give
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    stale_factor: i32,
+    /// Flag feeds giving no `<ttl>` whose newest item is older than this, e.g. 90d
+    #[arg(long, value_name = "DURATION", value_parser = fdr::parse_duration)]
+    stale_after: Option<chrono::TimeDelta>,
+}
+
+impl StaleArgs {
+    /// Tells why a feed whose newest item is from `newest` looks abandoned, if it does
+    fn check(
+        &self,
+        newest: Option<chrono::DateTime<chrono::FixedOffset>>,
+        ttl: Option<chrono::TimeDelta>,
+        now: chrono::DateTime<chrono::FixedOffset>,
+    ) -> Option<String> {
+        let age = now - newest?;
+        match ttl {
+            Some(ttl) if self.stale_factor > 0 => {
+                // TTLs too long to multiply never run out
+                let minutes = ttl.num_minutes().checked_mul(self.stale_factor.into())?;
+                let limit = chrono::TimeDelta::try_minutes(minutes)?;
+                (age > limit).then(|| {
+                    format!(
+                        "possibly stale, newest item is older than {} times its TTL of {} minutes",
+                        self.stale_factor,
+                        ttl.num_minutes()
+                    )
+                })
+            }
+            Some(_) => None,
+            None => {
+                let limit = self.stale_after?;
+                (age > limit)
+                    .then(|| "possibly stale, newest item is older than --stale-after".to_owned())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Args, Clone)]
+struct ShowNewsArgs {
+    /// OPML files to read, feeds present in several files are read once
+    #[arg(required = true)]
+    opml: Vec<String>,
+    #[arg(short, long, action = clap::ArgAction::SetTrue)]
     all: bool,
-    sort: SortMode,
+    /// Show items as usual, but don't mark them as seen
+    #[arg(long)]
+    dry_run: bool,
+    /// Neither read nor write the seen file or the listing for `fdr open`, so every
+    /// item is new, e.g. in CI. With --watch, later cycles still show only new items
+    #[arg(long, conflicts_with_all = ["seen_file", "since_last_run"])]
+    stateless: bool,
+    /// Show output in `$PAGER` when it is a terminal, the default
+    #[arg(long, overrides_with = "no_pager")]
+    pager: bool,
+    /// Print output directly, not in `$PAGER`
+    #[arg(long)]
+    no_pager: bool,
+    /// Don't end output with the count of new items
+    #[arg(long)]
+    no_summary: bool,
+    /// Print only `title<TAB>link` lines of items, without warnings unless `-v` is given
+    #[arg(short, long, conflicts_with = "format")]
+    quiet: bool,
+    /// Order of items: original, desc, asc, source or title, or several comma separated
+    /// keys applied in turn, e.g. `source,date-desc`
+    #[arg(short, long, value_name = "KEYS", default_value = "original", value_parser = parse_sort_spec)]
+    sort: SortSpec,
+    /// Reverse the order produced by --sort, so `original` shows the last feed first.
+    /// Applies before --limit and --per-source
+    #[arg(long)]
+    reverse: bool,
+    /// Read only feeds in this category, e.g. `News/World`
+    #[arg(long, value_name = "NAME")]
+    category: Option<String>,
+    #[command(flatten)]
+    fetch: FetchArgs,
+    /// Keep items repeated across feeds instead of showing each story once
+    #[arg(long)]
+    no_dedup: bool,
+    /// Strip `utm_*` and other tracking parameters from item links.
+    /// `--format json` keeps the links given by feeds in `original_link`
+    #[arg(long)]
+    clean_links: bool,
+    /// With --clean-links, replace FeedBurner links by the articles they redirect to
+    #[arg(long, requires = "clean_links")]
+    resolve_links: bool,
+    /// Show at most N items, counted after sorting and seen filtering
+    #[arg(long)]
+    limit: Option<usize>,
+    /// Read at most N newest items of every feed, in document order for undated ones
+    #[arg(long, value_name = "N")]
+    per_feed_limit: Option<usize>,
+    /// Show only items published at or after DATE, e.g. 2024-01-15 or 3d.
+    /// Items without date are dropped
+    #[arg(long, value_name = "DATE", value_parser = fdr::parse_date_bound)]
+    since: Option<fdr::DateBound>,
+    /// Show only items published since the last run with this flag, everything the
+    /// first time. Items without date are dropped
+    #[arg(long, conflicts_with = "since")]
+    since_last_run: bool,
+    /// Show only items published before DATE, e.g. 2024-01-15 or 12h
+    #[arg(long, value_name = "DATE", value_parser = fdr::parse_date_bound)]
+    until: Option<fdr::DateBound>,
+    /// Show only items at most this old, e.g. 6h. Items without date are dropped
+    #[arg(long, value_name = "DURATION", value_parser = fdr::parse_duration)]
+    max_age: Option<chrono::TimeDelta>,
+    /// Show only items at least this old, e.g. 1h
+    #[arg(long, value_name = "DURATION", value_parser = fdr::parse_duration)]
+    min_age: Option<chrono::TimeDelta>,
+    /// Show at most N items of every source
+    #[arg(long, value_name = "N")]
+    per_source: Option<usize>,
+    /// List items under a header of their source. Doesn't affect `json` output
+    #[arg(long)]
+    group_by_source: bool,
+    /// Keep only items whose title contains SUBSTRING (case-insensitive, repeatable)
+    #[arg(long, value_name = "SUBSTRING")]
+    filter: Vec<String>,
+    /// Drop items whose title contains SUBSTRING (case-insensitive, repeatable)
+    #[arg(long, value_name = "SUBSTRING")]
+    filter_out: Vec<String>,
+    /// Keep only items whose title matches PATTERN, e.g. `\brust\b` or `(?i)^release`
+    /// (repeatable)
+    #[arg(long, value_name = "PATTERN", value_parser = fdr::regex::Regex::new)]
+    filter_regex: Vec<fdr::regex::Regex>,
+    /// Let --filter match titles with typos, about one per four characters
+    #[arg(long, requires = "filter")]
+    fuzzy: bool,
+    /// Match --filter, --filter-out and --filter-regex against descriptions too
+    #[arg(long)]
+    match_description: bool,
+    /// Print how long every feed took to stderr, slowest first, also shown with -vv
+    #[arg(long)]
+    timings: bool,
+    /// Keep only items tagged NAME, e.g. by RSS `category` (case-insensitive, repeatable)
+    #[arg(long, value_name = "NAME")]
+    tag: Vec<String>,
+    /// Keep only items of feeds declaring language CODE, e.g. `en` for `en-US` too (repeatable)
+    #[arg(long, value_name = "CODE")]
+    language: Vec<String>,
+    /// Print tags of the fetched items with their counts instead of the items
+    #[arg(long, conflicts_with_all = ["format", "quiet"])]
+    list_tags: bool,
+    /// Drop items of the source named NAME (case-insensitive, repeatable)
+    #[arg(long, value_name = "NAME")]
+    exclude_source: Vec<String>,
+    /// Show only items of the source named NAME (case-insensitive, repeatable)
+    #[arg(long, value_name = "NAME")]
+    only_source: Vec<String>,
+    /// File with already seen items. Defaults to a per-OPML file in the user data directory
+    #[arg(long, value_name = "PATH")]
+    seen_file: Option<PathBuf>,
+    /// Forget seen items after this long, e.g. 90d or 12w
+    #[arg(long, value_name = "DURATION", default_value = "180d", value_parser = fdr::parse_duration)]
+    seen_retention: chrono::TimeDelta,
+    /// Directory for cached feed responses. Defaults to the user cache directory
+    #[arg(long, value_name = "PATH")]
+    cache_dir: Option<PathBuf>,
+    /// Read feeds fetched less than DURATION ago from the cache, without a request
+    #[arg(long, value_name = "DURATION", value_parser = fdr::parse_duration)]
+    cache_ttl: Option<chrono::TimeDelta>,
+    /// Read feeds only from the cache, feeds that aren't cached fail
+    #[arg(long)]
+    offline: bool,
+    /// Print item descriptions under titles
+    #[arg(long)]
+    show_description: bool,
+    /// Print authors of items after their titles
+    #[arg(long)]
+    show_author: bool,
+    /// Show titles written in all caps in sentence case, keeping acronyms
+    #[arg(long)]
+    normalize_titles: bool,
+    /// Print URLs of attached media, e.g. podcast audio, under titles
+    #[arg(long)]
+    enclosures: bool,
+    /// Keep only items with audio or video attached, set by `episodes`
+    #[arg(skip)]
+    episodes_only: bool,
+    /// Cut descriptions to this many characters
+    #[arg(long, value_name = "CHARS", default_value_t = 200)]
+    description_length: usize,
+    /// How dates of items are shown
+    #[arg(long, value_enum, default_value = "relative")]
+    date_format: DateFormat,
+    /// strftime pattern of absolute dates
+    #[arg(long, value_name = "PATTERN", default_value = fdr::DEFAULT_DATE_PATTERN, value_parser = fdr::parse_date_pattern)]
+    date_pattern: String,
+    /// Time zone from the global `--timezone`
+    #[arg(skip)]
+    timezone: Option<fdr::tz::TimeZone>,
+    /// Language from the global `--locale`
+    #[arg(skip)]
+    locale: fdr::Locale,
+    /// Keep running and check feeds for new items every INTERVAL, e.g. 5m.
+    /// Feeds whose responses say they stay fresh longer, with `Cache-Control: max-age`
+    /// or `Expires`, are checked once that ends
+    #[arg(long, value_name = "INTERVAL", value_parser = fdr::parse_duration)]
+    watch: Option<chrono::TimeDelta>,
+    /// Send desktop notifications about new items of every watch cycle
+    #[arg(long, requires = "watch")]
+    notify: bool,
+    /// Update URLs of feeds that moved permanently in the OPML files
+    #[arg(long)]
+    fix_redirects: bool,
+    /// Prefix items with their number, usable with `fdr open`
+    #[arg(long)]
+    numbered: bool,
+    /// Output format. `json` prints an array of shown items, `ndjson` an object per
+    /// line as soon as it's ready, `csv` a row per item with a header row, `markdown`
+    /// a digest with a list item per item
+    #[arg(long, value_enum, default_value = "text")]
+    format: OutputFormat,
+    /// Write items to FILE instead of stdout, without colors unless `CLICOLOR_FORCE`
+    /// is set. With --watch items of every cycle are appended
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// Resolves where seen items are stored: `--seen-file` if given,
+/// otherwise `<data dir>/seen/<hash of canonical OPML paths>.txt`.
+/// Every set of OPML files gets its own state, regardless of their order
+fn resolve_seen_file(opml: &[String], seen_file: Option<&Path>) -> Result<PathBuf, Box<dyn Error>> {
+    if let Some(path) = seen_file {
+        return Ok(path.to_owned());
+    }
+    let dirs = directories::ProjectDirs::from("", "", "fdr")
+        .ok_or("Can't find user data directory, use --seen-file")?;
+    Ok(dirs
+        .data_dir()
+        .join("seen")
+        .join(format!("{}.txt", opml_set_key(opml))))
+}
+
+/// Where the last listing of these OPML files is kept for `fdr open`
+fn listing_file(opml: &[String]) -> Option<PathBuf> {
+    let dirs = directories::ProjectDirs::from("", "", "fdr")?;
+    Some(
+        dirs.data_dir()
+            .join("last")
+            .join(format!("{}.txt", opml_set_key(opml))),
+    )
+}
+
+/// Where `show-news --since-last-run` keeps the time of the last run with these OPML files
+fn last_run_file(opml: &[String]) -> Result<PathBuf, Box<dyn Error>> {
+    let dirs =
+        directories::ProjectDirs::from("", "", "fdr").ok_or("Can't find user data directory")?;
+    Ok(dirs
+        .data_dir()
+        .join("runs")
+        .join(format!("{}.txt", opml_set_key(opml))))
+}
+
+/// Reads time saved by [`save_last_run`], `None` before the first run
+fn load_last_run(
+    path: &Path,
+) -> Result<Option<chrono::DateTime<chrono::FixedOffset>>, Box<dyn Error>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(Some(
+            chrono::DateTime::parse_from_rfc3339(content.trim())
+                .map_err(|err| format!("{}: {}", path.display(), err))?,
+        )),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!("Can't read {}: {}", path.display(), err).into()),
+    }
+}
+
+fn save_last_run(path: &Path, time: chrono::DateTime<chrono::FixedOffset>) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let time = time.to_rfc3339_opts(chrono::SecondsFormat::Secs, false);
+    std::fs::write(path, format!("{}\n", time))
+}
+
+/// Where `fdr bookmark` saves items, shared by all OPML files
+fn bookmarks_file() -> Result<PathBuf, Box<dyn Error>> {
+    let dirs =
+        directories::ProjectDirs::from("", "", "fdr").ok_or("Can't find user data directory")?;
+    Ok(dirs.data_dir().join("bookmarks.json"))
+}
+
+/// Identifies a set of OPML files regardless of their order or how paths are spelled
+fn opml_set_key(opml: &[String]) -> String {
+    let mut paths: Vec<String> = opml
+        .iter()
+        .map(|opml| {
+            let path = std::fs::canonicalize(opml).unwrap_or_else(|_| PathBuf::from(opml));
+            path.to_string_lossy().into_owned()
+        })
+        .collect();
+    paths.sort();
+    paths.dedup();
+    format!("{:016x}", fdr::stable_hash(paths.join("\n").as_bytes()))
+}
+
+/// Reads OPML documents, from stdin and URLs too, see [`fdr::load_opml`]
+async fn read_opmls(
+    sources: &[String],
+    client: &fdr::FeedClient,
+) -> Result<Vec<fdr::Opml>, Box<dyn Error>> {
+    let mut opmls = Vec::new();
+    for source in sources {
+        opmls.push(fdr::load_opml(client, source).await?);
+    }
+    Ok(opmls)
+}
+
+async fn show_news(
+    mut args: ShowNewsArgs,
     now: chrono::DateTime<chrono::FixedOffset>,
-) {
-    let opml = fdr::read_opml(opml).unwrap();
-    let mut previous_guids = Vec::<String>::new();
-    // read seen from file
-    let seen_file = "seen.txt";
-    if let Ok(content) = std::fs::read_to_string(seen_file) {
-        previous_guids = content.lines().map(|s| s.to_string()).collect();
-    }
-    let rss_outlines = fdr::get_rss_outlines(&opml);
+) -> Result<(), Box<dyn Error>> {
+    let client = args.fetch.build_client()?;
+    let opmls = read_opmls(&args.opml, &client).await?;
+    let seen_file = (!args.stateless)
+        .then(|| resolve_seen_file(&args.opml, args.seen_file.as_deref()))
+        .transpose()?;
+    let mut seen = match &seen_file {
+        Some(path) => fdr::SeenStore::load(path)?,
+        None => fdr::SeenStore::default(),
+    };
+    let listing = listing_file(&args.opml).filter(|_| !args.stateless);
+    let rss_outlines = select_outlines(&opmls, args.category.as_deref())?;
+    let mut cache = http_cache(args.cache_dir.clone());
+    if let Some(ttl) = args.cache_ttl {
+        cache = cache.map(|cache| cache.with_ttl(ttl.to_std().unwrap_or_default()));
+    }
+    if args.offline {
+        cache = Some(
+            cache
+                .ok_or("Can't find user cache directory, use --cache-dir")?
+                .offline(),
+        );
+    }
+    let last_run = if args.since_last_run {
+        let path = last_run_file(&args.opml)?;
+        args.since = load_last_run(&path)?.map(fdr::DateBound::At);
+        Some(path)
+    } else {
+        None
+    };
+    if let Some(path) = &args.output {
+        // fail before fetching, not after, but leave the file as it is until there's output
+        let writable = match args.watch {
+            Some(_) => std::fs::OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(path)
+                .map(drop),
+            None if path.exists() => std::fs::OpenOptions::new().write(true).open(path).map(drop),
+            None => std::fs::metadata(output_dir(path)).map(drop),
+        };
+        writable.map_err(|err| format!("Can't write {}: {}", path.display(), err))?;
+    }
+
+    let Some(interval) = args.watch else {
+        let items = fetch_items(
+            &rss_outlines,
+            &client,
+            cache.as_ref(),
+            &args.fetch,
+            &ItemsOptions::of(&args),
+            None,
+        )
+        .await;
+        seen.adopt_legacy_ids(&items);
+        let items = clean_links(items, &client, &seen, &args).await;
+        seen.prune(now - args.seen_retention);
+        show_items(items, &mut seen, &args, listing.as_deref(), now);
+        if let Some(seen_file) = seen_file.as_ref().filter(|_| !args.dry_run) {
+            seen.save(seen_file)?;
+            if let Some(path) = &last_run {
+                save_last_run(path, now)?;
+            }
+        }
+        return Ok(());
+    };
+
+    if interval <= chrono::TimeDelta::zero() {
+        return Err("Watch interval must be positive".into());
+    }
+    // `now` may be in a non-local time zone, so cycles advance it instead of reading the clock
+    let started = std::time::Instant::now();
+    let mut schedule = Schedule::new(interval.to_std()?);
+    let mut interval = tokio::time::interval(interval.to_std()?);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        let items = tokio::select! {
+            tick = interval.tick() => {
+                // the scheduled time of the tick, so feeds due at the next one aren't missed
+                schedule.cycle = tick.into_std();
+                fetch_items(
+                    &rss_outlines,
+                    &client,
+                    cache.as_ref(),
+                    &args.fetch,
+                    &ItemsOptions::of(&args),
+                    Some(&mut schedule),
+                )
+                .await
+            }
+            _ = &mut ctrl_c => break,
+        };
+        seen.adopt_legacy_ids(&items);
+        let items = clean_links(items, &client, &seen, &args).await;
+        let now = now + chrono::TimeDelta::from_std(started.elapsed())?;
+        seen.prune(now - args.seen_retention);
+        show_items(items, &mut seen, &args, listing.as_deref(), now);
+        // in memory items are still marked, so later cycles show only new ones
+        if let Some(seen_file) = seen_file.as_ref().filter(|_| !args.dry_run) {
+            seen.save(seen_file)?;
+            if let Some(path) = &last_run {
+                save_last_run(path, now)?;
+            }
+        }
+    }
+    if let Some(seen_file) = seen_file.as_ref().filter(|_| !args.dry_run) {
+        seen.save(seen_file)?;
+    }
+    Ok(())
+}
+
+/// Strips tracking parameters of item links with `--clean-links`, first resolving
+/// FeedBurner links of items that will be shown with `--resolve-links`
+async fn clean_links(
+    mut items: Vec<fdr::FeedItem>,
+    client: &fdr::FeedClient,
+    seen: &fdr::SeenStore,
+    args: &ShowNewsArgs,
+) -> Vec<fdr::FeedItem> {
+    if !args.clean_links {
+        return items;
+    }
+    if args.resolve_links {
+        let resolved: Vec<_> = stream::iter(items.iter().enumerate())
+            .filter(|(_, item)| {
+                let shown = args.all || !seen.contains_item(item);
+                std::future::ready(shown && fdr::is_feedproxy_link(&item.link))
+            })
+            .map(|(index, item)| async move { (index, client.resolve_redirects(&item.link).await) })
+            .buffer_unordered(args.fetch.concurrency.get())
+            .collect()
+            .await;
+        for (index, result) in resolved {
+            match result {
+                Ok(link) => items[index].set_link(link),
+                Err(err) => log::warn!("Failed to resolve {}: {}", items[index].link, err),
+            }
+        }
+    }
+    for item in &mut items {
+        let link = fdr::strip_tracking_params(&item.link);
+        item.set_link(link);
+    }
+    items
+}
+
+/// Cache of feed responses in `dir`, by default in the user cache directory
+fn http_cache(dir: Option<PathBuf>) -> Option<fdr::HttpCache> {
+    dir.or_else(|| {
+        let dirs = directories::ProjectDirs::from("", "", "fdr")?;
+        Some(dirs.cache_dir().join("http"))
+    })
+    .map(fdr::HttpCache::new)
+}
+
+/// Set while a progress line is on stderr, so log records clear it first
+static PROGRESS_SHOWN: AtomicBool = AtomicBool::new(false);
+
+/// `fetched N/M feeds` line on stderr, updated as feeds complete in any order
+struct Progress {
+    done: AtomicUsize,
+    total: usize,
+    enabled: bool,
+}
+
+impl Progress {
+    /// Progress shown only when stderr is a terminal and colors aren't disabled
+    fn new(total: usize, enabled: bool) -> Self {
+        let enabled = enabled
+            && std::io::stderr().is_terminal()
+            && colored::control::SHOULD_COLORIZE.should_colorize();
+        Progress {
+            done: AtomicUsize::new(0),
+            total,
+            enabled,
+        }
+    }
+
+    fn advance(&self) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.enabled {
+            eprint!("\r\x1b[2Kfetched {}/{} feeds", done, self.total);
+            PROGRESS_SHOWN.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Clears the line, so output printed next starts on an empty line
+    fn finish(&self) {
+        if PROGRESS_SHOWN.swap(false, Ordering::Relaxed) {
+            eprint!("\r\x1b[2K");
+        }
+    }
+}
+
+/// How `fetch_items` reports on stderr besides logging
+#[derive(Clone, Copy, PartialEq)]
+enum Report {
+    /// Shows how many feeds were fetched, when stderr is a terminal
+    Progress,
+    Quiet,
+    /// Prints timings as JSON, for `--format json`
+    Json,
+}
+
+/// What `fetch_items` does besides reading feeds, most of it set by `show-news` options
+struct ItemsOptions<'a> {
+    /// OPML files updated with feeds that moved permanently
+    fix_redirects: Option<&'a [String]>,
+    per_feed_limit: Option<usize>,
+    report: Report,
+    timings: bool,
+}
+
+impl<'a> ItemsOptions<'a> {
+    fn of(args: &'a ShowNewsArgs) -> Self {
+        let report = if args.format.is_json() {
+            Report::Json
+        } else if args.quiet {
+            Report::Quiet
+        } else {
+            Report::Progress
+        };
+        ItemsOptions {
+            fix_redirects: args.fix_redirects.then_some(&args.opml[..]),
+            per_feed_limit: args.per_feed_limit,
+            report,
+            timings: args.timings,
+        }
+    }
+
+    /// Options of commands other than `show-news`
+    fn reporting(report: Report) -> Self {
+        ItemsOptions {
+            fix_redirects: None,
+            per_feed_limit: None,
+            report,
+            timings: false,
+        }
+    }
+}
+
+/// When feeds are fetched again in watch mode: once the freshness their responses
+/// advertise runs out or, for responses that don't tell, after the watch interval.
+/// Feeds are never fetched more often than the interval, whatever they advertise
+struct Schedule {
+    interval: std::time::Duration,
+    /// Start of the current cycle, feeds due by then are fetched in it
+    cycle: std::time::Instant,
+    next: HashMap<String, std::time::Instant>,
+}
+
+impl Schedule {
+    fn new(interval: std::time::Duration) -> Self {
+        Schedule {
+            interval,
+            cycle: std::time::Instant::now(),
+            next: HashMap::new(),
+        }
+    }
+
+    fn is_due(&self, url: &str) -> bool {
+        self.next.get(url).is_none_or(|next| *next <= self.cycle)
+    }
+
+    fn fetched(&mut self, url: &str, fresh_for: Option<std::time::Duration>) {
+        let next = self.cycle + fresh_for.unwrap_or(self.interval).max(self.interval);
+        self.next.insert(url.to_owned(), next);
+    }
+}
+
+/// Reads items of all feeds, reporting the feeds that failed, as `options` tell.
+/// With `schedule`, only feeds due are fetched
+async fn fetch_items(
+    outlines: &[fdr::FeedOutline<'_>],
+    client: &fdr::FeedClient,
+    cache: Option<&fdr::HttpCache>,
+    fetch: &FetchArgs,
+    options: &ItemsOptions<'_>,
+    mut schedule: Option<&mut Schedule>,
+) -> Vec<fdr::FeedItem> {
     let mut all_items = Vec::<fdr::FeedItem>::new();
-    for outline in rss_outlines {
-        let channel = fdr::read_feed(&outline.xml_url).await.unwrap();
-        let items = fdr::read_feed_items(&channel);
-        all_items.extend(items);
+    let mut failed = Vec::new();
+    // feeds that were read fine but have no items, told apart from failed ones
+    let mut empty = Vec::new();
+    let mut moved = Vec::new();
+    let due: Vec<_> = outlines
+        .iter()
+        .filter(|outline| {
+            schedule
+                .as_deref()
+                .is_none_or(|schedule| schedule.is_due(outline.xml_url))
+        })
+        .collect();
+    if due.len() < outlines.len() {
+        log::info!(
+            "Feeds still fresh, not fetched: {}",
+            outlines.len() - due.len()
+        );
+    }
+    let progress = &Progress::new(due.len(), options.report == Report::Progress);
+    let started = std::time::Instant::now();
+    let mut timings = Vec::new();
+    // results come in OPML order, which the original sort mode relies on
+    let feeds = fdr::fetch_feeds(due.iter().copied(), client, cache, fetch.concurrency.get());
+    futures_util::pin_mut!(feeds);
+    while let Some((outline, result, elapsed)) = feeds.next().await {
+        progress.advance();
+        timings.push((outline, elapsed, result.is_ok()));
+        match result {
+            Ok(fetched) => {
+                let mut read =
+                    fdr::read_feed_items(&fetched.feed, outline.outline.name(), outline.xml_url);
+                if let Some(limit) = options.per_feed_limit {
+                    read.keep_newest(limit);
+                }
+                let title = fetched.feed.title();
+                for err in &read.errors {
+                    log::warn!("Invalid item in feed {}: {}", title, err);
+                }
+                log::debug!("Read {} items from {}", read.items.len(), title);
+                if read.items.is_empty() && read.errors.is_empty() {
+                    empty.push(outline.outline.display_name());
+                }
+                all_items.extend(read.items);
+                if let Some(schedule) = schedule.as_deref_mut() {
+                    schedule.fetched(outline.xml_url, fetched.fresh_for);
+                }
+                if let Some(moved_to) = fetched.moved_to {
+                    moved.push((outline, moved_to));
+                }
+            }
+            Err(err) => failed.push((outline, err)),
+        }
     }
-    match sort {
-        SortMode::Original => {}
-        SortMode::Desc => {
-            all_items.sort_by(|a, b| b.pub_date.cmp(&a.pub_date));
+    progress.finish();
+    for (outline, err) in &failed {
+        // the URL is already in the message
+        let reason = match err {
+            fdr::Error::HttpStatus { status, .. } => format!("HTTP {}", status),
+            err => err.to_string(),
+        };
+        log::warn!(
+            "Failed to read feed {} ({}): {}",
+            outline.outline.display_name(),
+            fdr::redact_url(outline.xml_url),
+            reason
+        );
+    }
+    if let Some(files) = options.fix_redirects.filter(|_| !moved.is_empty()) {
+        let moved: Vec<_> = moved
+            .iter()
+            .map(|(outline, moved_to)| (outline.xml_url, moved_to.as_str()))
+            .collect();
+        if let Err(err) = update_moved_feeds(files, &moved) {
+            log::warn!("Failed to update moved feeds: {}", err);
+        }
+    } else {
+        for (outline, moved_to) in &moved {
+            log::warn!(
+                "Feed {} moved permanently to {}, update the OPML or pass --fix-redirects",
+                fdr::redact_url(outline.xml_url),
+                fdr::redact_url(moved_to)
+            );
         }
-        SortMode::Asc => {
-            all_items.sort_by(|a, b| a.pub_date.cmp(&b.pub_date));
+    }
+    if !empty.is_empty() {
+        log::info!("Feeds read fine but without items: {}", empty.join(", "));
+    }
+    if options.timings || log::log_enabled!(log::Level::Debug) {
+        print_timings(timings, started.elapsed(), options.report);
+    }
+    if !failed.is_empty() {
+        log::warn!(
+            "Read {} of {} feeds, {} failed",
+            due.len() - failed.len(),
+            due.len(),
+            failed.len()
+        );
+    }
+    all_items
+}
+
+/// Prints how long feeds took to stderr, slowest first, and the total time
+fn print_timings(
+    mut timings: Vec<(&fdr::FeedOutline<'_>, std::time::Duration, bool)>,
+    total: std::time::Duration,
+    report: Report,
+) {
+    use fdr::json::Json;
+
+    timings.sort_by_key(|(_, elapsed, _)| std::cmp::Reverse(*elapsed));
+    // milliseconds are plenty for telling slow feeds apart
+    let seconds = |duration: std::time::Duration| {
+        Json::Number((duration.as_secs_f64() * 1000.0).round() / 1000.0)
+    };
+    if report == Report::Json {
+        let feeds = timings.iter().map(|(outline, elapsed, ok)| {
+            Json::object([
+                ("source", Json::from(outline.outline.display_name())),
+                (
+                    "url",
+                    Json::from(fdr::redact_url(outline.xml_url).into_owned()),
+                ),
+                ("seconds", seconds(*elapsed)),
+                ("ok", Json::from(*ok)),
+            ])
+        });
+        let output = Json::object([
+            ("feeds", Json::Array(feeds.collect())),
+            ("total_seconds", seconds(total)),
+        ]);
+        eprintln!("{}", output);
+        return;
+    }
+    eprintln!("Timings, slowest first:");
+    for (outline, elapsed, ok) in &timings {
+        let line = format!(
+            "{:>8.2}s  {} ({})",
+            elapsed.as_secs_f64(),
+            outline.outline.display_name(),
+            fdr::redact_url(outline.xml_url)
+        );
+        if *ok {
+            eprintln!("{}", line);
+        } else {
+            eprintln!("{} {}", line, "failed".red());
+        }
+    }
+    eprintln!(
+        "Total {:.2}s for {} feeds",
+        total.as_secs_f64(),
+        timings.len()
+    );
+}
+
+/// Prints tags of `items` with the number of items having them, most used first.
+/// Tags differing in case are counted together under the first spelling
+fn print_tags(items: &[fdr::FeedItem]) {
+    let mut tags: Vec<(String, usize)> = Vec::new();
+    for tag in items.iter().flat_map(|item| &item.categories) {
+        let lower = tag.to_lowercase();
+        match tags
+            .iter_mut()
+            .find(|(known, _)| known.to_lowercase() == lower)
+        {
+            Some((_, count)) => *count += 1,
+            None => tags.push((tag.clone(), 1)),
         }
     }
+    tags.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    if tags.is_empty() {
+        println!("No tags found");
+    }
+    for (tag, count) in tags {
+        println!("{:>5}  {}", count, tag);
+    }
+}
 
+/// Matcher of `--filter` and `--filter-out`: text contains any of `needles`, ignoring
+/// case and with `fuzzy` some typos, see [`fdr::fuzzy_contains`]
+fn contains_any(needles: &[String], fuzzy: bool) -> impl Fn(&str) -> bool + '_ {
+    move |text| {
+        let text = text.to_lowercase();
+        needles.iter().any(|needle| match fuzzy {
+            true => fdr::fuzzy_contains(&text, needle),
+            false => text.contains(&needle.to_lowercase()),
+        })
+    }
+}
+
+/// Filters, sorts and prints items, marking shown ones as seen
+fn show_items(
+    mut all_items: Vec<fdr::FeedItem>,
+    seen: &mut fdr::SeenStore,
+    args: &ShowNewsArgs,
+    listing: Option<&Path>,
+    now: chrono::DateTime<chrono::FixedOffset>,
+) {
+    if !args.no_dedup {
+        fdr::dedup_items(&mut all_items);
+    }
+    if args.list_tags {
+        print_tags(&all_items);
+        return;
+    }
+    let is_named = |names: &[String], item: &fdr::FeedItem| {
+        let source = item.source_name.to_lowercase();
+        names.iter().any(|name| name.to_lowercase() == source)
+    };
+    for name in args.exclude_source.iter().chain(&args.only_source) {
+        if !all_items
+            .iter()
+            .any(|item| is_named(std::slice::from_ref(name), item))
+        {
+            log::warn!("No items of a source named '{}'", name);
+        }
+    }
+    all_items.retain(|item| !is_named(&args.exclude_source, item));
+    if !args.only_source.is_empty() {
+        all_items.retain(|item| is_named(&args.only_source, item));
+    }
+    if !args.filter.is_empty() {
+        let matches = contains_any(&args.filter, args.fuzzy);
+        all_items.retain(|item| item.text_matches(args.match_description, &matches));
+    }
+    if !args.filter_regex.is_empty() {
+        let matches = |text: &str| args.filter_regex.iter().any(|regex| regex.is_match(text));
+        all_items.retain(|item| item.text_matches(args.match_description, matches));
+    }
+    if !args.tag.is_empty() {
+        all_items.retain(|item| item.has_any_tag(&args.tag));
+    }
+    if !args.language.is_empty() {
+        all_items.retain(|item| item.in_any_language(&args.language));
+    }
+    let matches = contains_any(&args.filter_out, false);
+    all_items.retain(|item| !item.text_matches(args.match_description, &matches));
+    if args.episodes_only {
+        all_items.retain(|item| {
+            item.enclosure
+                .as_ref()
+                .is_some_and(fdr::Enclosure::is_media)
+        });
+    }
+    let since = args.since.map(|bound| bound.resolve(now));
+    let until = args.until.map(|bound| bound.resolve(now));
+    if since.is_some() || until.is_some() {
+        all_items.retain(|item| {
+            item.pub_date.is_some_and(|date| {
+                since.is_none_or(|since| date >= since) && until.is_none_or(|until| date < until)
+            })
+        });
+    }
+    if args.max_age.is_some() || args.min_age.is_some() {
+        all_items.retain(|item| {
+            item.pub_date.is_some_and(|date| {
+                let age = now - date;
+                args.max_age.is_none_or(|max| age <= max)
+                    && args.min_age.is_none_or(|min| age >= min)
+            })
+        });
+    }
+
+    sort_items(&mut all_items, &args.sort);
+    if args.reverse {
+        all_items.reverse();
+    }
+
+    let mut shown = Vec::new();
+    // items passing all filters but cut by --limit, they stay unseen
+    let mut cut = 0;
+    let mut per_source = HashMap::<String, usize>::new();
     for item in all_items {
-        let guid = item.get_id();
-        let already_seen = previous_guids.iter().any(|g| *g == guid);
-        if !already_seen || all {
-            item.show(now, already_seen);
-            previous_guids.push(guid.clone());
+        let already_seen = seen.contains_item(&item);
+        if already_seen && !args.all {
+            continue;
+        }
+        let source_count = per_source.entry(item.source_name.clone()).or_default();
+        if args.per_source.is_some_and(|limit| *source_count >= limit) {
+            continue;
+        }
+        *source_count += 1;
+        if args.limit.is_some_and(|limit| shown.len() >= limit) {
+            cut += 1;
+            continue;
+        }
+        seen.insert_item(&item, now);
+        shown.push((item, already_seen));
+    }
+
+    let grouped = args.group_by_source && !args.format.is_json();
+    if grouped {
+        // sources come in order of their first item, items keep the sort order
+        let mut sources: Vec<String> = Vec::new();
+        for (item, _) in &shown {
+            if !sources.contains(&item.source_name) {
+                sources.push(item.source_name.clone());
+            }
+        }
+        shown.sort_by_key(|(item, _)| sources.iter().position(|s| *s == item.source_name));
+    }
+    if let Some(listing) = listing {
+        if let Err(err) = fdr::save_listing(listing, shown.iter().map(|(item, _)| item)) {
+            log::warn!("Can't save listing to {}: {}", listing.display(), err);
+        }
+    }
+    if args.notify {
+        notify::new_items(
+            shown
+                .iter()
+                .filter(|(_, already_seen)| !already_seen)
+                .map(|(item, _)| item),
+        );
+    }
+
+    if args.format == OutputFormat::Json {
+        let json_items = shown
+            .iter()
+            .map(|(item, already_seen)| item.to_json(*already_seen))
+            .collect();
+        let output = format!("{}\n", fdr::json::Json::Array(json_items));
+        print_output(&output, args);
+        return;
+    }
+    if let Some(path) = args
+        .output
+        .as_ref()
+        .filter(|_| shown.is_empty() && args.watch.is_none())
+    {
+        log::info!("No items to write, {} is left as it was", path.display());
+        return;
+    }
+    if args.format == OutputFormat::Ndjson && args.watch.is_none() && args.output.is_some() {
+        let output: String = shown
+            .iter()
+            .map(|(item, already_seen)| format!("{}\n", item.to_json(*already_seen)))
+            .collect();
+        write_output(&output, args);
+        return;
+    }
+    if args.format == OutputFormat::Ndjson {
+        // never paged, and flushed after every line, so log processors get items at once
+        let mut sink: Box<dyn Write> = match &args.output {
+            Some(path) => match std::fs::OpenOptions::new().append(true).open(path) {
+                Ok(file) => Box::new(file),
+                Err(err) => {
+                    log::error!("Can't write {}: {}", path.display(), err);
+                    return;
+                }
+            },
+            None => Box::new(std::io::stdout().lock()),
+        };
+        for (item, already_seen) in &shown {
+            let written =
+                writeln!(sink, "{}", item.to_json(*already_seen)).and_then(|()| sink.flush());
+            if written.is_err() {
+                break;
+            }
+        }
+        return;
+    }
+    if args.format == OutputFormat::Csv {
+        let mut output = fdr::CSV_HEADER.to_owned();
+        for (item, already_seen) in &shown {
+            output.push_str(&fdr::render_csv(item, *already_seen));
+        }
+        print_output(&output, args);
+        return;
+    }
+    if args.quiet {
+        let output: String = shown.iter().map(|(item, _)| item.render_quiet()).collect();
+        write_output(&output, args);
+        return;
+    }
+    if args.format == OutputFormat::Markdown {
+        let mut output = format!("# Digest {}\n\n", now.format("%Y-%m-%d"));
+        let mut source = None;
+        for (item, _) in &shown {
+            if grouped && source != Some(&item.source_name) {
+                // blank lines keep the lists of sources apart
+                if source.is_some() {
+                    output.push('\n');
+                }
+                let header = fdr::escape_markdown(&item.source_name);
+                output.push_str(&format!("## {}\n\n", header));
+                source = Some(&item.source_name);
+            }
+            output.push_str(&fdr::render_markdown(item, now, grouped, args.locale));
+        }
+        print_output(&output, args);
+        return;
+    }
+    let show_options = fdr::ShowOptions {
+        description_length: args.show_description.then_some(args.description_length),
+        hide_source: args.group_by_source,
+        show_author: args.show_author,
+        show_enclosure: args.enclosures || args.episodes_only,
+        date_style: match args.date_format {
+            DateFormat::Relative => fdr::DateStyle::Relative,
+            DateFormat::Absolute => fdr::DateStyle::Absolute,
+            DateFormat::Both => fdr::DateStyle::Both,
+            DateFormat::Calendar => fdr::DateStyle::Calendar,
+        },
+        date_pattern: Some(args.date_pattern.clone()),
+        timezone: args.timezone.clone(),
+        locale: args.locale,
+        normalize_titles: args.normalize_titles,
+    };
+    let mut output = String::new();
+    let mut source = None;
+    for (number, (item, already_seen)) in shown.iter().enumerate() {
+        if grouped && source != Some(&item.source_name) {
+            output.push_str(&format!("{}\n", item.source_name.bold().underline()));
+            source = Some(&item.source_name);
+        }
+        if args.numbered {
+            output.push_str(&format!("{:>4} ", format!("{}.", number + 1).dimmed()));
+        }
+        output.push_str(&item.render(now, *already_seen, &show_options));
+    }
+    if cut > 0 {
+        let more = format!("... and {} more, raise --limit to see them", cut);
+        output.push_str(&format!("{}\n", more.dimmed()));
+    }
+    if !args.all && !args.no_summary {
+        let new_items = shown.iter().filter(|(_, already_seen)| !already_seen);
+        // watching would repeat the line every cycle without anything new
+        if let Some(summary) = summary(new_items.map(|(item, _)| item), args.watch.is_none()) {
+            output.push_str(&format!("{}\n", summary.dimmed()));
+        }
+    }
+    print_output(&output, args);
+}
+
+/// Characters of source names shown in [`summary`], so a few long ones don't fill the line
+const SUMMARY_NAME_CHARS: usize = 30;
+
+/// Counts new items total and by source, e.g. `3 new items from 2 feeds: Blog 2, News 1`.
+/// Markup some feeds put in their titles is dropped from source names
+fn summary<'a>(
+    new_items: impl Iterator<Item = &'a fdr::FeedItem>,
+    when_none: bool,
+) -> Option<String> {
+    let mut sources: Vec<(&str, usize)> = Vec::new();
+    for item in new_items {
+        match sources
+            .iter_mut()
+            .find(|(name, _)| *name == item.source_name)
+        {
+            Some((_, count)) => *count += 1,
+            None => sources.push((&item.source_name, 1)),
+        }
+    }
+    if sources.is_empty() {
+        return when_none.then(|| "No new items".to_owned());
+    }
+    // stable sort keeps sources with equal counts in the order they were shown
+    sources.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    let total: usize = sources.iter().map(|(_, count)| count).sum();
+    let by_source: Vec<String> = sources
+        .iter()
+        .map(|(name, count)| {
+            let name = fdr::truncate_chars(&fdr::strip_html(name), SUMMARY_NAME_CHARS);
+            format!("{} {}", name, count)
+        })
+        .collect();
+    Some(format!(
+        "{} new {} from {} {}: {}",
+        total,
+        if total == 1 { "item" } else { "items" },
+        sources.len(),
+        if sources.len() == 1 { "feed" } else { "feeds" },
+        by_source.join(", ")
+    ))
+}
+
+/// Prints output of `show-news`, in the pager unless it's disabled or watching,
+/// where the pager would hold up the next cycles, or writing to `--output`
+fn print_output(output: &str, args: &ShowNewsArgs) {
+    if args.no_pager || args.watch.is_some() || args.output.is_some() || output.is_empty() {
+        write_output(output, args);
+    } else if let Err(err) = page(output) {
+        log::warn!("Pager failed: {}", err);
+    }
+}
+
+/// Prints output of `show-news`, or writes it to the `--output` file: appended
+/// when watching, otherwise replacing the file once the output is complete
+fn write_output(output: &str, args: &ShowNewsArgs) {
+    let Some(path) = &args.output else {
+        print!("{}", output);
+        return;
+    };
+    let forced = std::env::var("CLICOLOR_FORCE").is_ok_and(|value| value != "0");
+    let output = if forced {
+        Cow::Borrowed(output)
+    } else {
+        strip_ansi(output)
+    };
+    let written = match args.watch {
+        Some(_) => std::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(output.as_bytes())),
+        None => replace_file(path, output.as_bytes()),
+    };
+    if let Err(err) = written {
+        log::error!("Can't write {}: {}", path.display(), err);
+    }
+}
+
+/// Directory `path` is written in, the current one for bare file names
+fn output_dir(path: &Path) -> &Path {
+    path.parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+/// Writes `contents` to a temporary file next to `path` and renames it to `path`,
+/// so readers see the old contents or the new ones, never a partial file
+fn replace_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = output_dir(path).join(format!(".{}.{}.tmp", name, std::process::id()));
+    let replaced = std::fs::write(&temp, contents).and_then(|()| std::fs::rename(&temp, path));
+    if replaced.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    replaced
+}
+
+/// Removes ANSI escape sequences of colors and styles from `text`
+fn strip_ansi(text: &str) -> Cow<'_, str> {
+    if !text.contains('\x1b') {
+        return Cow::Borrowed(text);
+    }
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+        // CSI sequences end with a byte of @ to ~, e.g. `m` of `\x1b[1;31m`
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    Cow::Owned(stripped)
+}
+
+/// Opens link of item number `index` of the last listing of `opml`
+fn open_item(opml: Vec<String>, index: NonZeroUsize) -> Result<(), Box<dyn Error>> {
+    let item = listed_item(&opml, index)?;
+    log::info!("Opening {} ({})", item.link, item.id);
+    open_in_browser(&item.link)
+}
+
+/// Item number `index` of the last listing of `opml` files
+fn listed_item(opml: &[String], index: NonZeroUsize) -> Result<fdr::ListedItem, Box<dyn Error>> {
+    let path = listing_file(opml).ok_or("Can't find user data directory")?;
+    let listing = match fdr::load_listing(&path) {
+        Ok(listing) => listing,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err("No listing of these OPML files yet, run show-news first".into())
+        }
+        Err(err) => return Err(format!("Can't read {}: {}", path.display(), err).into()),
+    };
+    let items = listing.len();
+    listing.into_iter().nth(index.get() - 1).ok_or_else(|| {
+        format!(
+            "No item {} in the last listing, it has {} items",
+            index, items
+        )
+        .into()
+    })
+}
+
+/// Saves item number `index` of the last listing of `opml` to bookmarks
+fn bookmark_item(opml: Vec<String>, index: NonZeroUsize) -> Result<(), Box<dyn Error>> {
+    let item = listed_item(&opml, index)?;
+    let path = bookmarks_file()?;
+    let mut bookmarks = fdr::bookmarks::Bookmarks::load(&path)?;
+    let title = if item.title.is_empty() {
+        item.link.clone()
+    } else {
+        item.title.clone()
+    };
+    let added = bookmarks.add(fdr::bookmarks::Bookmark {
+        id: item.id,
+        title: item.title,
+        link: item.link,
+        source: item.source_name,
+        date: item.pub_date,
+    });
+    if added {
+        bookmarks.save(&path)?;
+        println!("Bookmarked {}", title);
+    } else {
+        println!("{} is already bookmarked", title);
+    }
+    Ok(())
+}
+
+/// Lists bookmarks, or removes the one with id `remove`
+fn list_bookmarks(
+    remove: Option<String>,
+    now: chrono::DateTime<chrono::FixedOffset>,
+    locale: fdr::Locale,
+) -> Result<(), Box<dyn Error>> {
+    let path = bookmarks_file()?;
+    let mut bookmarks = fdr::bookmarks::Bookmarks::load(&path)?;
+    if let Some(id) = remove {
+        if !bookmarks.remove(&id) {
+            return Err(format!("No bookmark with id '{}'", id).into());
+        }
+        bookmarks.save(&path)?;
+        println!("Removed bookmark {}", id);
+        return Ok(());
+    }
+    if bookmarks.items.is_empty() {
+        println!("No bookmarks, add one with `fdr bookmark`");
+    }
+    for bookmark in &bookmarks.items {
+        let date = bookmark.date.map_or("date unknown".to_owned(), |date| {
+            fdr::date_diff(now - date, locale)
+        });
+        let source = if bookmark.source.is_empty() {
+            String::new()
+        } else {
+            format!("{}: ", bookmark.source)
+        };
+        println!(
+            "{}{} ({}) {}",
+            source,
+            bookmark.title.bold(),
+            date.dimmed(),
+            bookmark.link
+        );
+        println!("    {} {}", "id:".dimmed(), bookmark.id);
+    }
+    Ok(())
+}
+
+/// Fetches feeds again to show full content of a listed item
+async fn read_item(
+    opml: Vec<String>,
+    index: NonZeroUsize,
+    fetch: FetchArgs,
+    now: chrono::DateTime<chrono::FixedOffset>,
+    locale: fdr::Locale,
+) -> Result<(), Box<dyn Error>> {
+    let listed = listed_item(&opml, index)?;
+    let client = fetch.build_client()?;
+    let opmls = read_opmls(&opml, &client).await?;
+    let outlines = select_outlines(&opmls, None)?;
+    let cache = http_cache(None);
+    let items = fetch_items(
+        &outlines,
+        &client,
+        cache.as_ref(),
+        &fetch,
+        &ItemsOptions::reporting(Report::Quiet),
+        None,
+    )
+    .await;
+    let item = items
+        .iter()
+        .find(|item| listed.is(item))
+        .or_else(|| {
+            let link = fdr::normalize_url(&listed.link);
+            items
+                .iter()
+                .find(|item| fdr::normalize_url(&item.link) == link)
+        })
+        .ok_or_else(|| {
+            format!(
+                "Item {} is no longer in its feed, `fdr open` still opens {}",
+                index, listed.link
+            )
+        })?;
+    let body = match (&item.content, &item.description) {
+        (Some(content), _) => fdr::html_to_text(content),
+        (None, Some(description)) => description.clone(),
+        (None, None) => format!("No content, the item is at {}", item.link),
+    };
+    let date = item.pub_date.map_or("date unknown".to_owned(), |date| {
+        fdr::date_diff(now - date, locale)
+    });
+    page(&format!(
+        "{}\n{} ({})\n{}\n\n{}\n",
+        item.title, item.source_name, date, item.link, body
+    ))
+}
+
+/// Shows `text` in `$PAGER`, `less -R` by default, when stdout is a terminal.
+/// Like git, sets `LESS=FRX` when unset so text fitting the screen is just printed
+fn page(text: &str) -> Result<(), Box<dyn Error>> {
+    use std::io::Write;
+    if !std::io::stdout().is_terminal() {
+        print!("{}", text);
+        return Ok(());
+    }
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| "less -R".to_owned());
+    // `$PAGER` may have arguments, e.g. `less -S`
+    let mut words = pager.split_whitespace();
+    let program = words.next().unwrap_or_default();
+    let mut command = std::process::Command::new(program);
+    command.args(words).stdin(std::process::Stdio::piped());
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    let child = command.spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) => {
+            log::debug!("Can't run pager {}: {}", pager, err);
+            print!("{}", text);
+            return Ok(());
+        }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // the pager may quit before reading everything, that's not an error
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    child.wait()?;
+    Ok(())
+}
+
+/// Opens `url` with `$BROWSER` or the platform's default handler
+fn open_in_browser(url: &str) -> Result<(), Box<dyn Error>> {
+    let default = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    let opener = std::env::var("BROWSER")
+        .ok()
+        .filter(|browser| !browser.is_empty())
+        .unwrap_or_else(|| default.to_owned());
+    let status = std::process::Command::new(&opener)
+        .arg(url)
+        .status()
+        .map_err(|err| format!("Can't run {}: {}", opener, err))?;
+    if !status.success() {
+        return Err(format!("{} failed with {}", opener, status).into());
+    }
+    Ok(())
+}
+
+/// Marks items of all feeds as seen
+async fn mark_read(
+    opml: Vec<String>,
+    category: Option<String>,
+    seen_file: Option<PathBuf>,
+    fetch: FetchArgs,
+    now: chrono::DateTime<chrono::FixedOffset>,
+) -> Result<(), Box<dyn Error>> {
+    let client = fetch.build_client()?;
+    let opmls = read_opmls(&opml, &client).await?;
+    let seen_file = resolve_seen_file(&opml, seen_file.as_deref())?;
+    let mut seen = fdr::SeenStore::load(&seen_file)?;
+    let outlines = select_outlines(&opmls, category.as_deref())?;
+    let cache = http_cache(None);
+    let items = fetch_items(
+        &outlines,
+        &client,
+        cache.as_ref(),
+        &fetch,
+        &ItemsOptions::reporting(Report::Quiet),
+        None,
+    )
+    .await;
+    seen.adopt_legacy_ids(&items);
+    let mut marked = 0;
+    for item in &items {
+        if !seen.contains_item(item) {
+            seen.insert_item(item, now);
+            marked += 1;
+        }
+    }
+    seen.save(&seen_file)?;
+    println!("Marked {} items as read", marked);
+    Ok(())
+}
+
+/// Fetches items of all feeds and shows them in `fdr tui`, newest first
+async fn browse(
+    opml: Vec<String>,
+    category: Option<String>,
+    seen_file: Option<PathBuf>,
+    fetch: FetchArgs,
+    now: chrono::DateTime<chrono::FixedOffset>,
+    locale: fdr::Locale,
+) -> Result<(), Box<dyn Error>> {
+    let client = fetch.build_client()?;
+    let opmls = read_opmls(&opml, &client).await?;
+    let seen_file = resolve_seen_file(&opml, seen_file.as_deref())?;
+    let mut seen = fdr::SeenStore::load(&seen_file)?;
+    let outlines = select_outlines(&opmls, category.as_deref())?;
+    let cache = http_cache(None);
+    let mut items = fetch_items(
+        &outlines,
+        &client,
+        cache.as_ref(),
+        &fetch,
+        &ItemsOptions::reporting(Report::Progress),
+        None,
+    )
+    .await;
+    seen.adopt_legacy_ids(&items);
+    fdr::dedup_items(&mut items);
+    sort_items(&mut items, &SortSpec(vec![SortKey::DateDesc]));
+    #[cfg(unix)]
+    {
+        tui::run(items, &mut seen, now, locale)?;
+        seen.save(&seen_file)?;
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (items, seen, seen_file, now);
+        Err("fdr tui isn't supported on this platform".into())
+    }
+}
+
+/// Removes item from seen items, `item` being its id or link
+fn mark_unread(
+    opml: Vec<String>,
+    item: String,
+    seen_file: Option<PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    let seen_file = resolve_seen_file(&opml, seen_file.as_deref())?;
+    let mut seen = fdr::SeenStore::load(&seen_file)?;
+    let mut removed = seen.remove(&item);
+    if !removed {
+        // the seen file has ids only, links are known from the last listing
+        let listing = listing_file(&opml).and_then(|path| fdr::load_listing(&path).ok());
+        let link = fdr::normalize_url(&item);
+        for listed in listing
+            .iter()
+            .flatten()
+            .filter(|listed| fdr::normalize_url(&listed.link) == link)
+        {
+            removed |= match &listed.feed_url {
+                Some(feed_url) => seen.remove_from_feed(feed_url, &listed.id),
+                None => seen.remove(&listed.id),
+            };
+        }
+    }
+    if !removed {
+        return Err(format!("Item '{}' isn't marked as read", item).into());
+    }
+    seen.save(&seen_file)?;
+    println!("Marked {} as unread", item);
+    Ok(())
+}
+
+/// Result of checking a single feed
+struct FeedHealth {
+    status: String,
+    items: Option<usize>,
+    newest: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// Why the feed failed, `None` when it's healthy
+    problem: Option<String>,
+    redirects: Vec<fdr::Redirect>,
+    moved_to: Option<String>,
+    ttl: Option<chrono::TimeDelta>,
+    /// Items skipped because they couldn't be read
+    invalid_items: usize,
+}
+
+async fn check_feed(client: &fdr::FeedClient, url: &str) -> FeedHealth {
+    let mut health = FeedHealth {
+        status: "-".to_owned(),
+        items: None,
+        newest: None,
+        problem: None,
+        redirects: Vec::new(),
+        moved_to: None,
+        ttl: None,
+        invalid_items: 0,
+    };
+    let response = match fdr::fetch_feed(client, url).await {
+        Ok(response) => response,
+        Err(fdr::Error::TooManyRedirects(max)) => {
+            let problem = format!("redirect loop or too many hops, gave up after {}", max);
+            health.problem = Some(problem);
+            return health;
+        }
+        Err(err) => {
+            health.problem = Some(format!("request failed: {}", err));
+            return health;
+        }
+    };
+    health.status = response.status.as_u16().to_string();
+    health.redirects = response.redirects.clone();
+    health.moved_to = response.moved_to.clone();
+    if !response.status.is_success() {
+        health.problem = Some(format!("HTTP {}", response.status));
+        return health;
+    }
+    match fdr::Feed::parse(&response.body[..]) {
+        Ok(feed) => {
+            let read = fdr::read_feed_items(&feed, None, url);
+            health.items = Some(read.items.len());
+            health.newest = read.items.iter().filter_map(|item| item.pub_date).max();
+            health.invalid_items = read.errors.len();
+            health.ttl = feed.ttl();
+        }
+        Err(err) => health.problem = Some(format!("parse failed: {}", err)),
+    }
+    health
+}
+
+async fn check_feeds(
+    opml_file: String,
+    fix: bool,
+    stale: StaleArgs,
+    fetch: FetchArgs,
+    now: chrono::DateTime<chrono::FixedOffset>,
+) -> Result<(), Box<dyn Error>> {
+    let client = fetch.build_client()?;
+    let client = &client;
+    let opml = fdr::load_opml(client, &opml_file).await?;
+    let outlines = fdr::get_rss_outlines(&opml);
+    let progress = &Progress::new(outlines.len(), true);
+    let results: Vec<_> = stream::iter(&outlines)
+        .map(|outline| async move {
+            let health = check_feed(client, outline.xml_url).await;
+            progress.advance();
+            (outline, health)
+        })
+        .buffered(fetch.concurrency.get())
+        .collect()
+        .await;
+    progress.finish();
+
+    println!("{:<6} {:>5}  {:<16} SOURCE", "STATUS", "ITEMS", "NEWEST");
+    let mut failed = 0;
+    let mut empty = 0;
+    for (outline, health) in &results {
+        let items = health.items.map_or("-".to_owned(), |n| n.to_string());
+        let newest = health.newest.map_or("-".to_owned(), |date| {
+            fdr::date_diff(now - date, fdr::Locale::En)
+        });
+        let line = format!(
+            "{:<6} {:>5}  {:<16} {} ({})",
+            health.status,
+            items,
+            newest,
+            outline.outline.display_name(),
+            fdr::redact_url(outline.xml_url)
+        );
+        if let Some(problem) = &health.problem {
+            failed += 1;
+            println!("{} {}", line.red(), problem.red());
+        } else if health.items == Some(0) && health.invalid_items == 0 {
+            empty += 1;
+            println!("{} {}", line, "parsed, but no items".yellow());
+        } else if health.invalid_items > 0 {
+            let note = format!("{} invalid items skipped", health.invalid_items);
+            println!("{} {}", line, note.yellow());
+        } else if let Some(note) = stale.check(health.newest, health.ttl, now) {
+            println!("{} {}", line, note.yellow());
+        } else {
+            println!("{}", line);
+        }
+        for redirect in &health.redirects {
+            let hop = format!(
+                "       {} -> {}",
+                redirect.status.as_u16(),
+                fdr::redact_url(&redirect.location)
+            );
+            println!("{}", hop.yellow());
+        }
+        if let Some(moved_to) = &health.moved_to {
+            println!(
+                "{}",
+                format!("       moved permanently to {}", fdr::redact_url(moved_to)).yellow()
+            );
+        }
+    }
+    let empty = if empty > 0 {
+        format!(", {} without items", empty)
+    } else {
+        String::new()
+    };
+    println!(
+        "{} feeds checked, {} ok, {} failed{}",
+        results.len(),
+        results.len() - failed,
+        failed,
+        empty
+    );
+    if fix {
+        let moved: Vec<_> = results
+            .iter()
+            .filter_map(|(outline, health)| Some((outline.xml_url, health.moved_to.as_deref()?)))
+            .collect();
+        update_moved_feeds(&[opml_file], &moved)?;
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} feeds failed", failed, results.len()).into());
+    }
+    Ok(())
+}
+
+async fn export(
+    input: String,
+    output: String,
+    drop_failing: bool,
+    fetch: FetchArgs,
+) -> Result<(), Box<dyn Error>> {
+    let client = fetch.build_client()?;
+    let client = &client;
+    let mut opml = fdr::load_opml(client, &input).await?;
+    if drop_failing {
+        let urls: Vec<String> = fdr::get_rss_outlines(&opml)
+            .iter()
+            .map(|outline| outline.xml_url.to_owned())
+            .collect();
+        let failing: HashSet<String> = stream::iter(urls)
+            .map(|url| async move {
+                let health = check_feed(client, &url).await;
+                (url, health)
+            })
+            .buffered(fetch.concurrency.get())
+            .filter_map(|(url, health)| async move {
+                let problem = health.problem?;
+                log::warn!("Dropping {}: {}", fdr::redact_url(&url), problem);
+                Some(url)
+            })
+            .collect()
+            .await;
+        opml.retain_feeds(|outline| !outline.feed_url().is_some_and(|url| failing.contains(url)));
+    }
+    if output == "-" {
+        print!("{}", opml.to_xml()?);
+    } else {
+        fdr::write_opml(&opml, &output)?;
+    }
+    Ok(())
+}
+
+/// Prints paths of the config file and state files, each with whether it exists,
+/// and the settings in effect
+fn print_paths(
+    opml: Vec<String>,
+    seen_file: Option<PathBuf>,
+    format: PathsFormat,
+    fetch: FetchArgs,
+    now: chrono::DateTime<chrono::FixedOffset>,
+    locale: fdr::Locale,
+) -> Result<(), Box<dyn Error>> {
+    use fdr::json::Json;
+    let per_opml = |path: Result<PathBuf, Box<dyn Error>>| path.ok().filter(|_| !opml.is_empty());
+    let cache_dir =
+        directories::ProjectDirs::from("", "", "fdr").map(|dirs| dirs.cache_dir().join("http"));
+    let paths = [
+        ("config_file", config_file()),
+        (
+            "seen_file",
+            per_opml(resolve_seen_file(&opml, seen_file.as_deref())),
+        ),
+        (
+            "listing_file",
+            listing_file(&opml).filter(|_| !opml.is_empty()),
+        ),
+        ("last_run_file", per_opml(last_run_file(&opml))),
+        ("bookmarks_file", bookmarks_file().ok()),
+        ("feed_stats_file", feed_stats_file().ok()),
+        ("cache_dir", cache_dir),
+    ];
+    let config = match config_file().filter(|path| path.exists()) {
+        Some(path) => fdr::config::Config::load(&path)?,
+        None => fdr::config::Config::default(),
+    };
+    let count = |count: usize| Json::Number(count as f64);
+    let settings = [
+        (
+            "opml",
+            Json::Array(opml.iter().map(|opml| Json::from(opml.as_str())).collect()),
+        ),
+        (
+            "sort",
+            Json::from(config.sort.unwrap_or_else(|| "original".to_owned())),
+        ),
+        (
+            "color",
+            Json::from(colored::control::SHOULD_COLORIZE.should_colorize()),
+        ),
+        ("concurrency", count(fetch.concurrency.get())),
+        ("timeout", Json::from(fdr::format_duration(fetch.timeout))),
+        ("max_redirects", count(fetch.max_redirects)),
+        ("retries", count(fetch.retries as usize)),
+        (
+            "per_host_concurrency",
+            count(fetch.per_host_concurrency.get()),
+        ),
+        (
+            "per_host_interval",
+            Json::from(fdr::format_duration(fetch.per_host_interval)),
+        ),
+        ("timezone", Json::from(now.offset().to_string())),
+        ("locale", Json::from(locale.code())),
+    ];
+    match format {
+        PathsFormat::Text => {
+            for (name, path) in &paths {
+                let path = match path {
+                    Some(path) if path.exists() => format!("{}", path.display()),
+                    Some(path) => format!("{} (missing)", path.display()),
+                    None => "-".to_owned(),
+                };
+                println!("{:<22} {}", name, path);
+            }
+            for (name, value) in &settings {
+                let value = match value {
+                    Json::String(text) => text.clone(),
+                    Json::Array(values) => {
+                        let values: Vec<&str> = values.iter().filter_map(Json::as_str).collect();
+                        values.join(", ")
+                    }
+                    other => other.to_string(),
+                };
+                println!("{:<22} {}", name, value);
+            }
+        }
+        PathsFormat::Json => {
+            let paths = paths.into_iter().map(|(name, path)| {
+                let path = path.map_or(Json::Null, |path| {
+                    Json::object([
+                        ("path", Json::from(path.to_string_lossy().into_owned())),
+                        ("exists", Json::from(path.exists())),
+                    ])
+                });
+                (name, path)
+            });
+            let output = Json::object([
+                ("paths", Json::object(paths)),
+                ("settings", Json::object(settings)),
+            ]);
+            println!("{}", output);
+        }
+    }
+    Ok(())
+}
+
+async fn merge(inputs: Vec<String>, output: String) -> Result<(), Box<dyn Error>> {
+    let client = fdr::build_client(&fdr::ClientOptions::default())?;
+    let mut opmls = Vec::new();
+    for input in &inputs {
+        opmls.push(fdr::load_opml(&client, input).await?);
+    }
+    let feeds: usize = opmls
+        .iter()
+        .map(|opml| fdr::get_rss_outlines(opml).len())
+        .sum();
+    let merged = fdr::merge_opmls(opmls);
+    let kept = fdr::get_rss_outlines(&merged).len();
+    if output == "-" {
+        print!("{}", merged.to_xml()?);
+    } else {
+        fdr::write_opml(&merged, &output)?;
+        println!(
+            "Merged {} feeds of {} files into {}, dropped {} duplicates",
+            kept,
+            inputs.len(),
+            output,
+            feeds - kept
+        );
+    }
+    Ok(())
+}
+
+async fn dedup_check(opml_file: String, fix: bool) -> Result<(), Box<dyn Error>> {
+    if fix && !fdr::is_opml_file(&opml_file) {
+        return Err("Only OPML files can be fixed, not stdin or URLs".into());
+    }
+    let client = fdr::build_client(&fdr::ClientOptions::default())?;
+    let mut opml = fdr::load_opml(&client, &opml_file).await?;
+    let groups = fdr::duplicate_feeds(&opml);
+    for group in &groups {
+        println!("{}", fdr::redact_url(group[0].xml_url).bold());
+        for feed in group {
+            let category = if feed.category.is_empty() {
+                "top level".to_owned()
+            } else {
+                feed.category.join("/")
+            };
+            println!(
+                "  {} ({}) in {}",
+                feed.outline.display_name(),
+                fdr::redact_url(feed.xml_url),
+                category
+            );
+        }
+    }
+    let duplicates: usize = groups.iter().map(|group| group.len() - 1).sum();
+    if duplicates == 0 {
+        println!("No duplicate feeds");
+        return Ok(());
+    }
+    if !fix {
+        return Err(format!("{} duplicate feeds, pass --fix to remove them", duplicates).into());
+    }
+    let removed = opml.remove_duplicate_feeds();
+    fdr::write_opml(&opml, &opml_file)?;
+    println!("Removed {} duplicate feeds from {}", removed, opml_file);
+    Ok(())
+}
+
+/// Prints feeds found on the site at `url`, adding the new ones to `add_to` OPML file
+async fn discover(
+    url: String,
+    add_to: Option<String>,
+    fetch: FetchArgs,
+) -> Result<(), Box<dyn Error>> {
+    let client = fetch.build_client()?;
+    let feeds = fdr::discover_feeds(&client, &url).await?;
+    if feeds.is_empty() {
+        return Err(format!("No feeds found at {}", fdr::redact_url(&url)).into());
+    }
+    for feed in &feeds {
+        let feed_url = fdr::redact_url(&feed.url);
+        match &feed.title {
+            Some(title) => println!("{} {}", title.bold(), feed_url),
+            None => println!("{}", feed_url),
+        }
+    }
+    let Some(file) = add_to else {
+        return Ok(());
+    };
+    let mut opml = match fdr::read_opml(&file) {
+        Ok(opml) => opml,
+        Err(fdr::Error::Io { source, .. }) if source.kind() == std::io::ErrorKind::NotFound => {
+            fdr::Opml::new("Feeds")
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let subscribed: HashSet<String> = fdr::get_rss_outlines(&opml)
+        .iter()
+        .map(|feed| fdr::normalize_url(feed.xml_url))
+        .collect();
+    let mut added = 0;
+    for feed in feeds {
+        if subscribed.contains(&fdr::normalize_url(&feed.url)) {
+            println!("Already subscribed to {}", fdr::redact_url(&feed.url));
+            continue;
+        }
+        opml.body.outline.push(fdr::Outline {
+            text: Some(
+                feed.title
+                    .clone()
+                    .unwrap_or_else(|| fdr::strip_credentials(&feed.url).into_owned()),
+            ),
+            title: feed.title,
+            outline_type: Some("rss".to_owned()),
+            xml_url: Some(feed.url),
+            html_url: Some(fdr::strip_credentials(&url).into_owned()),
+            fdr_headers: None,
+            outline: Vec::new(),
+            unknown: fdr::Unknown::default(),
+        });
+        added += 1;
+    }
+    if added > 0 {
+        fdr::write_opml(&opml, &file)?;
+        println!("Added {} feeds to {}", added, file);
+    }
+    Ok(())
+}
+
+/// Rewrites OPML files so feeds point to `(old, new)` URLs they moved to.
+/// Files are read again, so changes made to them meanwhile are kept
+fn update_moved_feeds(files: &[String], moved: &[(&str, &str)]) -> Result<(), Box<dyn Error>> {
+    for file in files {
+        if !fdr::is_opml_file(file) {
+            log::warn!("Can't update feeds of OPML {}, it isn't a file", file);
+            continue;
+        }
+        let mut opml = fdr::read_opml(file)?;
+        let mut replaced = 0;
+        for (old, new) in moved {
+            replaced += opml.replace_feed_url(old, new);
+        }
+        if replaced > 0 {
+            fdr::write_opml(&opml, file)?;
+            log::warn!("Updated {} moved feed URLs in {}", replaced, file);
+        }
+    }
+    Ok(())
+}
+
+/// Returns RSS outlines of OPML files, only from `category` when given.
+/// Feeds listed several times are returned once
+fn select_outlines<'a>(
+    opmls: &'a [fdr::Opml],
+    category: Option<&str>,
+) -> Result<Vec<fdr::FeedOutline<'a>>, Box<dyn Error>> {
+    let mut urls = HashSet::new();
+    let selected: Vec<_> = opmls
+        .iter()
+        .flat_map(fdr::get_rss_outlines)
+        .filter(|outline| category.is_none_or(|category| outline.in_category(category)))
+        .filter(|outline| urls.insert(fdr::normalize_url(outline.xml_url)))
+        .collect();
+    let Some(category) = category.filter(|_| selected.is_empty()) else {
+        return Ok(selected);
+    };
+    let mut categories: Vec<String> = Vec::new();
+    for name in opmls.iter().flat_map(fdr::get_categories) {
+        if !categories.contains(&name) {
+            categories.push(name);
+        }
+    }
+    let available = if categories.is_empty() {
+        "none".to_owned()
+    } else {
+        categories.join(", ")
+    };
+    Err(format!(
+        "Category '{}' not found. Available categories: {}",
+        category, available
+    )
+    .into())
+}
+
+async fn show_sources(
+    opml: String,
+    category: Option<String>,
+    live: bool,
+    stats: bool,
+    stale: StaleArgs,
+    fetch: FetchArgs,
+    now: chrono::DateTime<chrono::FixedOffset>,
+) -> Result<(), Box<dyn Error>> {
+    let client = fetch.build_client()?;
+    let opml = fdr::load_opml(&client, &opml).await?;
+    // fail early with the list of categories when nothing matches
+    let outlines = select_outlines(std::slice::from_ref(&opml), category.as_deref())?;
+    let stats_file = if stats {
+        Some(feed_stats_file()?)
+    } else {
+        None
+    };
+    let details = if live || stats {
+        Some(SourceDetails {
+            sources: read_live_sources(&outlines, &client, &fetch).await,
+            live,
+            stats: match &stats_file {
+                Some(path) => Some(fdr::FeedStatsStore::load(path)?),
+                None => None,
+            },
+            stale,
+            now,
+        })
+    } else {
+        None
+    };
+    print_outline_tree(
+        &opml.body.outline,
+        &mut Vec::new(),
+        category.as_deref(),
+        details.as_ref(),
+    );
+    if let (Some(path), Some(details)) = (stats_file, details) {
+        let mut store = details.stats.unwrap_or_default();
+        for (url, source) in details.sources {
+            if let Ok(source) = source {
+                let newest = source.dates.iter().max().copied();
+                store.insert(
+                    url,
+                    fdr::FeedStats {
+                        newest,
+                        checked: now,
+                    },
+                );
+            }
+        }
+        store.save(&path)?;
+    }
+    Ok(())
+}
+
+/// Where `show-sources --stats` remembers the newest item of every feed
+fn feed_stats_file() -> Result<PathBuf, Box<dyn Error>> {
+    let dirs =
+        directories::ProjectDirs::from("", "", "fdr").ok_or("Can't find user data directory")?;
+    Ok(dirs.data_dir().join("feeds.txt"))
+}
+
+/// What a feed says about itself, as opposed to its OPML outline
+struct LiveSource {
+    title: String,
+    link: String,
+    description: String,
+    /// Dates of the dated items
+    dates: Vec<chrono::DateTime<chrono::FixedOffset>>,
+    items: usize,
+    ttl: Option<chrono::TimeDelta>,
+}
+
+/// Fetched feeds by URL with what `show-sources` prints of them
+struct SourceDetails {
+    sources: HashMap<String, Result<LiveSource, String>>,
+    /// Print title, link and description of feeds
+    live: bool,
+    /// Print age of the newest item and items added since the stats of the last run
+    stats: Option<fdr::FeedStatsStore>,
+    stale: StaleArgs,
+    now: chrono::DateTime<chrono::FixedOffset>,
+}
+
+/// Fetches feeds of `outlines` by their URL
+async fn read_live_sources(
+    outlines: &[fdr::FeedOutline<'_>],
+    client: &fdr::FeedClient,
+    fetch: &FetchArgs,
+) -> HashMap<String, Result<LiveSource, String>> {
+    stream::iter(outlines)
+        .map(|outline| async move {
+            let source = fdr::read_feed(client, outline.xml_url)
+                .await
+                .map(|fetched| {
+                    let items = fdr::read_feed_items(&fetched.feed, None, outline.xml_url).items;
+                    LiveSource {
+                        title: fetched.feed.title().to_owned(),
+                        link: fetched.feed.link().to_owned(),
+                        description: fetched.feed.description().to_owned(),
+                        dates: items.iter().filter_map(|item| item.pub_date).collect(),
+                        items: items.len(),
+                        ttl: fetched.feed.ttl(),
+                    }
+                })
+                .map_err(|err| err.to_string());
+            (outline.xml_url.to_owned(), source)
+        })
+        .buffer_unordered(fetch.concurrency.get())
+        .collect()
+        .await
+}
+
+/// Prints feeds and the categories containing them, indented by depth.
+/// With `category` only matching feeds and their parents are printed
+fn print_outline_tree<'a>(
+    outlines: &'a [fdr::Outline],
+    path: &mut Vec<&'a str>,
+    category: Option<&str>,
+    details: Option<&SourceDetails>,
+) {
+    let indent = "  ".repeat(path.len());
+    for outline in outlines {
+        if let Some(url) = outline.feed_url() {
+            if category.is_none_or(|category| fdr::category_matches(path, category)) {
+                let name = outline.display_name();
+                println!("{}{}", indent, name);
+                if let Some(details) = details {
+                    print_source_details(details, url, &name, &indent);
+                }
+            }
+        } else if !outline.outline.is_empty() {
+            path.push(outline.category_name());
+            if has_matching_feeds(&outline.outline, path, category) {
+                println!("{}{}", indent, outline.category_name().bold());
+                print_outline_tree(&outline.outline, path, category, details);
+            }
+            path.pop();
         }
     }
-    std::fs::write(seen_file, previous_guids.join("\n")).unwrap();
 }
 
-fn show_sources(opml: String) {
-    let opml = fdr::read_opml(&opml).unwrap();
-    let rss_outlines = fdr::get_rss_outlines(&opml);
-    for outline in rss_outlines {
-        println!("{}", outline.title);
+/// Prints feed details under its OPML name, titles differing from it are highlighted
+fn print_source_details(details: &SourceDetails, url: &str, name: &str, indent: &str) {
+    let source = match details.sources.get(url) {
+        Some(Ok(source)) => source,
+        Some(Err(err)) => {
+            println!("{}  {}", indent, err.red());
+            return;
+        }
+        None => return,
+    };
+    if details.live {
+        let title = if source.title == name {
+            source.title.normal()
+        } else {
+            source.title.yellow()
+        };
+        println!(
+            "{}  {} ({} items) {}",
+            indent, title, source.items, source.link
+        );
+        let description = fdr::strip_html(&source.description);
+        if !description.is_empty() {
+            println!(
+                "{}  {}",
+                indent,
+                fdr::truncate_chars(&description, 100).dimmed()
+            );
+        }
+        let newest = source.dates.iter().max().copied();
+        if let Some(stale) = details.stale.check(newest, source.ttl, details.now) {
+            println!("{}  {}", indent, stale.yellow());
+        }
+    }
+    if let Some(store) = &details.stats {
+        let newest = match source.dates.iter().max() {
+            Some(&newest) => format!(
+                "newest item {}",
+                fdr::date_diff(details.now - newest, fdr::Locale::En)
+            ),
+            None => "no dated items".to_owned(),
+        };
+        let since = match store.get(url) {
+            Some(last) => {
+                let added = source
+                    .dates
+                    .iter()
+                    .filter(|&&date| last.newest.is_none_or(|newest| date > newest))
+                    .count();
+                format!(
+                    "{} new since the last run {}",
+                    added,
+                    fdr::date_diff(details.now - last.checked, fdr::Locale::En)
+                )
+            }
+            None => "first run".to_owned(),
+        };
+        println!("{}  {}", indent, format!("{}, {}", newest, since).dimmed());
+    }
+}
+
+fn has_matching_feeds<'a>(
+    outlines: &'a [fdr::Outline],
+    path: &mut Vec<&'a str>,
+    category: Option<&str>,
+) -> bool {
+    outlines.iter().any(|outline| {
+        if outline.feed_url().is_some() {
+            return category.is_none_or(|category| fdr::category_matches(path, category));
+        }
+        path.push(outline.category_name());
+        let found = has_matching_feeds(&outline.outline, path, category);
+        path.pop();
+        found
+    })
+}
+
+/// Prints log records to stderr. Records of dependencies are shown only at `-vvv`
+struct StderrLogger {
+    level: log::LevelFilter,
+}
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+            && (metadata.target().starts_with("fdr") || self.level == log::LevelFilter::Trace)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let label = match record.level() {
+            log::Level::Error => "[ERROR]".red(),
+            log::Level::Warn => "[WARNING]".red(),
+            log::Level::Info => "[INFO]".normal(),
+            log::Level::Debug => "[DEBUG]".dimmed(),
+            log::Level::Trace => "[TRACE]".dimmed(),
+        };
+        if PROGRESS_SHOWN.swap(false, Ordering::Relaxed) {
+            eprint!("\r\x1b[2K");
+        }
+        eprintln!("{} {}", label, record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+/// Shows warnings by default, `-v` adds progress of fetching and `-vv` parsing details.
+/// `quiet` hides the warnings too
+fn init_logger(verbose: u8, quiet: bool) {
+    let level = match verbose {
+        0 if quiet => log::LevelFilter::Off,
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+    if log::set_boxed_logger(Box::new(StderrLogger { level })).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// Where settings are read from: `$FDR_CONFIG`, otherwise `<config dir>/config.toml`
+fn config_file() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("FDR_CONFIG").filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    let dirs = directories::ProjectDirs::from("", "", "fdr")?;
+    Some(dirs.config_dir().join("config.toml"))
+}
+
+/// Expands leading `~/` of paths in config to the home directory
+fn expand_home(path: &str) -> String {
+    let home = directories::BaseDirs::new();
+    match (path.strip_prefix("~/"), home) {
+        (Some(rest), Some(home)) => home.home_dir().join(rest).to_string_lossy().into_owned(),
+        _ => path.to_owned(),
     }
 }
 
+/// Makes config settings the defaults of matching options,
+/// so options given on the command line still win
+fn apply_config(
+    mut command: clap::Command,
+    config: &fdr::config::Config,
+) -> Result<clap::Command, Box<dyn Error>> {
+    if let Some(sort) = &config.sort {
+        parse_sort_spec(sort).map_err(|err| format!("Invalid sort in config: {}", err))?;
+    }
+    let opml: Vec<String> = config.opml.iter().map(|path| expand_home(path)).collect();
+    if config.color == Some(false) {
+        command = command.mut_arg("no_color", |arg| arg.default_value("true"));
+    }
+    if let Some(timezone) = &config.timezone {
+        command = command.mut_arg("timezone", |arg| arg.default_value(timezone));
+    }
+    if let Some(locale) = &config.locale {
+        command = command.mut_arg("locale", |arg| arg.default_value(locale));
+    }
+    for name in [
+        "show-news",
+        "episodes",
+        "show-sources",
+        "check",
+        "export",
+        "mark-read",
+        "read",
+        "discover",
+        "tui",
+        "paths",
+    ] {
+        command = command.mut_subcommand(name, |mut subcommand| {
+            if let Some(concurrency) = config.concurrency {
+                subcommand = subcommand.mut_arg("concurrency", |arg| {
+                    arg.default_value(concurrency.to_string())
+                });
+            }
+            if let Some(timeout) = &config.timeout {
+                subcommand = subcommand.mut_arg("timeout", |arg| arg.default_value(timeout));
+            }
+            if let Some(concurrency) = config.per_host_concurrency {
+                subcommand = subcommand.mut_arg("per_host_concurrency", |arg| {
+                    arg.default_value(concurrency.to_string())
+                });
+            }
+            if let Some(interval) = &config.per_host_interval {
+                subcommand =
+                    subcommand.mut_arg("per_host_interval", |arg| arg.default_value(interval));
+            }
+            subcommand
+        });
+    }
+    for name in ["show-news", "episodes"] {
+        if let Some(sort) = &config.sort {
+            command = command.mut_subcommand(name, |subcommand| {
+                subcommand.mut_arg("sort", |arg| arg.default_value(sort))
+            });
+        }
+    }
+    if !opml.is_empty() {
+        for name in ["show-news", "episodes", "mark-read", "tui", "paths"] {
+            command = command.mut_subcommand(name, |subcommand| {
+                subcommand.mut_arg("opml", |arg| {
+                    arg.required(false).default_values(opml.clone())
+                })
+            });
+        }
+    }
+    // commands reading a single OPML file default to it only when there's no choice
+    if let [opml] = &opml[..] {
+        for name in ["show-sources", "check", "dedup-check"] {
+            command = command.mut_subcommand(name, |subcommand| {
+                subcommand.mut_arg("opml", |arg| arg.required(false).default_value(opml))
+            });
+        }
+    }
+    Ok(command)
+}
+
+/// Parses command line with defaults from the config file
+fn parse_args() -> Result<Cli, Box<dyn Error>> {
+    let mut command = Cli::command();
+    if let Some(path) = config_file() {
+        let config = fdr::config::Config::load(&path)?;
+        command = apply_config(command, &config)?;
+    }
+    let matches = command.get_matches();
+    Ok(Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit()))
+}
+
 #[tokio::main]
 async fn main() {
-    let now = Local::now().fixed_offset();
-    let args = CLI::parse();
-    match args.operation {
-        Operation::ShowNews { opml, all, sort } => show_news(&opml, all, sort, now).await,
-        Operation::ShowSources { opml } => show_sources(opml),
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{} {}", "[ERROR]".red(), err);
+            std::process::exit(1);
+        }
+    };
+    let now = match &args.timezone {
+        Some(timezone) => timezone.convert(&Utc::now()),
+        None => Local::now().fixed_offset(),
+    };
+    let locale = args.locale.unwrap_or_else(fdr::Locale::from_env);
+    // `colored` already turns colors off for NO_COLOR and when stdout isn't a terminal
+    if args.no_color {
+        colored::control::set_override(false);
+    }
+    let quiet = matches!(
+        &args.operation,
+        Operation::ShowNews(news_args) | Operation::Episodes(news_args) if news_args.quiet
+    );
+    init_logger(args.verbose, quiet);
+    let result = match args.operation {
+        Operation::ShowNews(news_args) => {
+            let news_args = ShowNewsArgs {
+                timezone: args.timezone,
+                locale,
+                ..news_args
+            };
+            show_news(news_args, now).await
+        }
+        Operation::Episodes(news_args) => {
+            let news_args = ShowNewsArgs {
+                episodes_only: true,
+                timezone: args.timezone,
+                locale,
+                ..news_args
+            };
+            show_news(news_args, now).await
+        }
+        Operation::ShowSources {
+            opml,
+            category,
+            live,
+            stats,
+            stale,
+            fetch,
+        } => show_sources(opml, category, live, stats, stale, fetch, now).await,
+        Operation::Check {
+            opml,
+            fix_redirects,
+            stale,
+            fetch,
+        } => check_feeds(opml, fix_redirects, stale, fetch, now).await,
+        Operation::DedupCheck { opml, fix } => dedup_check(opml, fix).await,
+        Operation::Discover { url, add_to, fetch } => discover(url, add_to, fetch).await,
+        Operation::Tui {
+            opml,
+            category,
+            seen_file,
+            fetch,
+        } => browse(opml, category, seen_file, fetch, now, locale).await,
+        Operation::Open { opml, index } => open_item(opml, index),
+        Operation::Read { opml, index, fetch } => read_item(opml, index, fetch, now, locale).await,
+        Operation::Bookmark { opml, index } => bookmark_item(opml, index),
+        Operation::Bookmarks { remove } => list_bookmarks(remove, now, locale),
+        Operation::MarkRead {
+            opml,
+            category,
+            seen_file,
+            fetch,
+        } => mark_read(opml, category, seen_file, fetch, now).await,
+        Operation::MarkUnread {
+            opml,
+            item,
+            seen_file,
+        } => mark_unread(opml, item, seen_file),
+        Operation::Export {
+            input,
+            output,
+            drop_failing,
+            fetch,
+        } => export(input, output, drop_failing, fetch).await,
+        Operation::Merge { inputs, output } => merge(inputs, output).await,
+        Operation::Paths {
+            opml,
+            seen_file,
+            format,
+            fetch,
+        } => print_paths(opml, seen_file, format, fetch, now, locale),
+    };
+    if let Err(err) = result {
+        eprintln!("{} {}", "[ERROR]".red(), err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Item of an RSS feed of `source`, RFC 2822 `date` being its `<pubDate>`
+    fn item(source: &str, title: &str, guid: &str, date: Option<&str>) -> fdr::FeedItem {
+        let date = date.map_or(String::new(), |date| format!("<pubDate>{}</pubDate>", date));
+        let xml = format!(
+            r#"<rss version="2.0"><channel><title>{0}</title><link>https://{0}.example</link>
+                <item><title>{1}</title><link>https://{0}.example/{2}</link>
+                    <guid>{2}</guid>{3}</item>
+            </channel></rss>"#,
+            source, title, guid, date
+        );
+        let feed = fdr::Feed::parse(xml.as_bytes()).unwrap();
+        let url = format!("https://{}.example/feed", source);
+        fdr::read_feed_items(&feed, None, &url).items.remove(0)
+    }
+
+    fn guids(items: &[fdr::FeedItem]) -> Vec<String> {
+        items.iter().map(fdr::FeedItem::get_id).collect()
+    }
+
+    #[test]
+    fn parse_sort_keys() {
+        use SortKey::*;
+        assert_eq!(
+            parse_sort_spec("source,date-desc"),
+            Ok(SortSpec(vec![Source, DateDesc]))
+        );
+        assert_eq!(
+            parse_sort_spec(" Title , ASC"),
+            Ok(SortSpec(vec![Title, DateAsc]))
+        );
+        // a lone source key sorts newest first within sources
+        assert_eq!(
+            parse_sort_spec("source"),
+            Ok(SortSpec(vec![Source, DateDesc]))
+        );
+        assert_eq!(parse_sort_spec("desc"), Ok(SortSpec(vec![DateDesc])));
+        assert!(parse_sort_spec("newest")
+            .unwrap_err()
+            .starts_with("unknown sort key 'newest'"));
+        assert!(parse_sort_spec("desc,").is_err());
+    }
+
+    #[test]
+    fn sort_by_several_keys() {
+        let mut items = [
+            item("b", "B1", "b1", Some("Mon, 01 Jan 2024 10:00:00 GMT")),
+            item("a", "A1", "a1", Some("Tue, 02 Jan 2024 10:00:00 GMT")),
+            item("b", "B2", "b2", Some("Wed, 03 Jan 2024 10:00:00 GMT")),
+            item("a", "A2", "a2", Some("Mon, 01 Jan 2024 10:00:00 GMT")),
+        ];
+        sort_items(&mut items, &parse_sort_spec("source,date-asc").unwrap());
+        assert_eq!(guids(&items), ["a2", "a1", "b1", "b2"]);
+        sort_items(&mut items, &parse_sort_spec("source,desc").unwrap());
+        assert_eq!(guids(&items), ["a1", "a2", "b2", "b1"]);
+    }
+
+    #[test]
+    fn sort_undated_items_last() {
+        let mut items = [
+            item("a", "Undated", "u", None),
+            item("a", "Old", "o", Some("Mon, 01 Jan 2024 10:00:00 GMT")),
+            item("a", "New", "n", Some("Wed, 03 Jan 2024 10:00:00 GMT")),
+        ];
+        sort_items(&mut items, &parse_sort_spec("desc").unwrap());
+        assert_eq!(guids(&items), ["n", "o", "u"]);
+        sort_items(&mut items, &parse_sort_spec("asc").unwrap());
+        assert_eq!(guids(&items), ["o", "n", "u"]);
+    }
+
+    #[test]
+    fn sort_ties_broken_by_id() {
+        let date = Some("Mon, 01 Jan 2024 10:00:00 GMT");
+        let mut items = [
+            item("a", "Same", "3", date),
+            item("a", "Same", "1", date),
+            item("a", "Same", "2", date),
+        ];
+        sort_items(&mut items, &parse_sort_spec("title").unwrap());
+        assert_eq!(guids(&items), ["1", "2", "3"]);
+        // original order is kept with `original` among the keys
+        items.swap(0, 2);
+        sort_items(&mut items, &parse_sort_spec("title,original").unwrap());
+        assert_eq!(guids(&items), ["3", "2", "1"]);
+    }
+
+    fn fresh_for(cache_control: &str) -> Option<std::time::Duration> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            reqwest::header::CACHE_CONTROL,
+            cache_control.parse().unwrap(),
+        );
+        fdr::freshness(&headers)
+    }
+
+    #[test]
+    fn schedule_waits_for_max_age() {
+        let minute = std::time::Duration::from_secs(60);
+        let mut schedule = Schedule::new(minute);
+        let url = "https://a.example/feed";
+        assert!(schedule.is_due(url));
+        schedule.fetched(url, fresh_for("public, max-age=600"));
+        let start = schedule.cycle;
+        for (cycle, due) in [(1, false), (9, false), (10, true), (11, true)] {
+            schedule.cycle = start + minute * cycle;
+            assert_eq!(schedule.is_due(url), due, "cycle {}", cycle);
+        }
+        assert!(schedule.is_due("https://b.example/feed"));
+    }
+
+    #[test]
+    fn schedule_clamps_to_interval() {
+        let minute = std::time::Duration::from_secs(60);
+        let mut schedule = Schedule::new(minute);
+        let start = schedule.cycle;
+        schedule.fetched("short", fresh_for("max-age=5"));
+        schedule.fetched("no-cache", fresh_for("no-cache"));
+        schedule.fetched("missing", None);
+        for url in ["short", "no-cache", "missing"] {
+            schedule.cycle = start + std::time::Duration::from_secs(30);
+            assert!(!schedule.is_due(url), "{}", url);
+            schedule.cycle = start + minute;
+            assert!(schedule.is_due(url), "{}", url);
+        }
+    }
+
+    #[test]
+    fn strip_ansi_codes() {
+        assert!(matches!(strip_ansi("plain"), Cow::Borrowed("plain")));
+        assert_eq!(
+            strip_ansi("\x1b[1;4mBlog\x1b[0m\n\x1b[2m  1.\x1b[0m Post \x1b[31m•\x1b[0m"),
+            "Blog\n  1. Post •"
+        );
+    }
+
+    #[test]
+    fn replace_file_contents() {
+        let dir = std::env::temp_dir().join(format!("fdr-output-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("digest.md");
+        std::fs::write(&path, "old digest").unwrap();
+        replace_file(&path, b"new digest").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new digest");
+        let names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["digest.md"]);
+        // the temporary file goes next to the target, so a missing directory fails
+        assert!(replace_file(&dir.join("missing/digest.md"), b"new").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn summary_counts_by_source() {
+        let mut items = [
+            item("a", "A1", "a1", None),
+            item("b", "B1", "b1", None),
+            item("b", "B2", "b2", None),
+        ];
+        items[0].source_name = "Blog".to_owned();
+        for item in &mut items[1..] {
+            item.source_name = "<b>Tech</b> &amp; more".to_owned();
+        }
+        assert_eq!(
+            summary(items.iter(), true).as_deref(),
+            Some("3 new items from 2 feeds: Tech & more 2, Blog 1")
+        );
+        assert_eq!(
+            summary(items[..1].iter(), true).as_deref(),
+            Some("1 new item from 1 feed: Blog 1")
+        );
+        assert_eq!(summary([].iter(), true).as_deref(), Some("No new items"));
+        assert_eq!(summary([].iter(), false), None);
+    }
+
+    #[test]
+    fn summary_truncates_long_names() {
+        let mut items = [item("a", "A1", "a1", None)];
+        items[0].source_name = "Очень длинное название новостной ленты".to_owned();
+        assert_eq!(
+            summary(items.iter(), true).as_deref(),
+            Some("1 new item from 1 feed: Очень длинное название новостн… 1")
+        );
     }
 }
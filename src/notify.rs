@@ -0,0 +1,90 @@
+//! Desktop notifications of `show-news --watch --notify`, sent with `notify-send` on
+//! Linux and the BSDs and with `osascript` on macOS
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Notifications sent in a cycle at most, the rest are summed up in one
+const MAX_NOTIFICATIONS: usize = 5;
+
+/// Titles listed in a notification about several items of a source
+const MAX_TITLES: usize = 3;
+
+/// Set once sending failed, so the warning isn't repeated every cycle
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Notifies about `items`, one notification per source. Sources with several items
+/// get `N new from Source`, and sources past [`MAX_NOTIFICATIONS`] share one
+pub fn new_items<'a>(items: impl Iterator<Item = &'a fdr::FeedItem>) {
+    let mut sources: Vec<(&str, Vec<&fdr::FeedItem>)> = Vec::new();
+    for item in items {
+        match sources
+            .iter_mut()
+            .find(|(name, _)| *name == item.source_name)
+        {
+            Some((_, items)) => items.push(item),
+            None => sources.push((&item.source_name, vec![item])),
+        }
+    }
+    let rest = if sources.len() > MAX_NOTIFICATIONS {
+        sources.split_off(MAX_NOTIFICATIONS - 1)
+    } else {
+        Vec::new()
+    };
+    for (source, items) in &sources {
+        if let [item] = items[..] {
+            send(source, &format!("{}\n{}", item.title, item.link));
+        } else {
+            let mut titles: Vec<&str> = items
+                .iter()
+                .take(MAX_TITLES)
+                .map(|item| item.title.as_str())
+                .collect();
+            if items.len() > MAX_TITLES {
+                titles.push("…");
+            }
+            send(
+                &format!("{} new from {}", items.len(), source),
+                &titles.join("\n"),
+            );
+        }
+    }
+    if !rest.is_empty() {
+        let count: usize = rest.iter().map(|(_, items)| items.len()).sum();
+        let names: Vec<&str> = rest.iter().map(|(name, _)| *name).collect();
+        send(
+            &format!("{} new from {} more feeds", count, rest.len()),
+            &names.join(", "),
+        );
+    }
+}
+
+fn send(title: &str, body: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        let script = format!(
+            "display notification {} with title {}",
+            apple_script_string(body),
+            apple_script_string(title)
+        );
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(script);
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.arg("--app-name=fdr").arg(title).arg(body);
+        command
+    };
+    let result = match command.status() {
+        Ok(status) if status.success() => return,
+        Ok(status) => format!("exited with {}", status),
+        Err(err) => err.to_string(),
+    };
+    if !FAILED.swap(true, Ordering::Relaxed) {
+        let program = command.get_program().to_string_lossy().into_owned();
+        log::warn!("Can't send notifications with {}: {}", program, result);
+    }
+}
+
+/// Quotes text as AppleScript string literal
+fn apple_script_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,1016 @@
+//! OPML documents and plain feed lists: reading, writing and walking their feeds
+use crate::feed::{decode_xml, fetch_feed, redact_url, FeedClient};
+use crate::html::decode_entities;
+use crate::item::normalize_url;
+use crate::Error;
+use quick_xml::de::from_str;
+use quick_xml::events::Event;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct Opml {
+    #[serde(rename = "@version")]
+    pub version: String,
+    pub head: Head,
+    pub body: BodyList,
+    /// Other attributes of `<opml>` and comments outside `<head>` and `<body>`
+    #[serde(skip)]
+    pub unknown: Unknown,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct Head {
+    pub title: String,
+    /// Elements besides `<title>`, e.g. `<ownerName>`, and comments as raw XML
+    #[serde(skip)]
+    pub extra: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct BodyList {
+    #[serde(default)]
+    pub outline: Vec<Outline>,
+    /// Comments after the last outline as raw XML
+    #[serde(skip)]
+    pub trailing_comments: Vec<String>,
+}
+
+/// Parts of an OPML element fdr doesn't read, kept so rewriting a file doesn't lose them
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Unknown {
+    /// Attributes as `(name, value)`, the value escaped as in the document
+    pub attributes: Vec<(String, String)>,
+    /// Comments before the element as raw XML
+    pub comments: Vec<String>,
+    /// Comments after the last child of the element as raw XML
+    pub trailing_comments: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct Outline {
+    #[serde(rename = "@text")]
+    pub text: Option<String>,
+    #[serde(rename = "@title")]
+    pub title: Option<String>,
+    #[serde(rename = "@type")]
+    pub outline_type: Option<String>,
+    #[serde(rename = "@xmlUrl")]
+    pub xml_url: Option<String>,
+    #[serde(rename = "@htmlUrl")]
+    pub html_url: Option<String>,
+    /// Extra request headers of the feed as `Name: value` pairs separated by `|`,
+    /// e.g. `Authorization: Bearer abc`, see [`parse_headers`](crate::parse_headers)
+    #[serde(rename = "@fdrHeaders")]
+    pub fdr_headers: Option<String>,
+    /// Nested outlines, used by category (folder) outlines
+    #[serde(default)]
+    pub outline: Vec<Outline>,
+    /// Other attributes, e.g. `description`, and comments before and in the outline
+    #[serde(skip)]
+    pub unknown: Unknown,
+}
+
+/// Attributes of outlines [`Outline`] has fields for
+const OUTLINE_ATTRIBUTES: &[&[u8]] = &[
+    b"text",
+    b"title",
+    b"type",
+    b"xmlUrl",
+    b"htmlUrl",
+    b"fdrHeaders",
+];
+
+impl Outline {
+    /// Returns feed URL if this outline is an RSS feed
+    pub fn feed_url(&self) -> Option<&str> {
+        match self.outline_type.as_deref() {
+            Some("rss") => self.xml_url.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Name of the outline when used as a category
+    pub fn category_name(&self) -> &str {
+        self.text
+            .as_deref()
+            .or(self.title.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Name given in the OPML, that is title or, if it has none, text
+    pub fn name(&self) -> Option<&str> {
+        [self.title.as_deref(), self.text.as_deref()]
+            .into_iter()
+            .flatten()
+            .find(|name| !name.trim().is_empty())
+    }
+
+    /// Name to show for the outline: title, then text, then host of the feed URL
+    pub fn display_name(&self) -> String {
+        self.name()
+            .map(|name| name.to_owned())
+            .or_else(|| {
+                let url = reqwest::Url::parse(self.xml_url.as_deref()?).ok()?;
+                url.host_str().map(|host| host.to_owned())
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// RSS outline of OPML with the category outlines it is nested in
+pub struct FeedOutline<'a> {
+    pub outline: &'a Outline,
+    pub xml_url: &'a str,
+    /// Category names, outermost first. Empty for top level feeds
+    pub category: Vec<&'a str>,
+}
+
+/// Reads OPML file, errors name the file and, where possible, the line of the problem
+pub fn read_opml(file: &str) -> Result<Opml, Error> {
+    let content = std::fs::read(file).map_err(|source| Error::Io {
+        path: file.into(),
+        source,
+    })?;
+    parse_subscriptions(&content, file).map_err(|err| opml_error_in(err, file))
+}
+
+/// Reads OPML from `source`, which is `-` for stdin, an `http(s)://` URL or a file path.
+/// Headers its feeds need are registered with `client`, see [`FeedClient::add_feed_headers`]
+pub async fn load_opml(client: &FeedClient, source: &str) -> Result<Opml, Error> {
+    let opml = read_opml_from(client, source).await?;
+    client
+        .add_feed_headers(&opml)
+        .map_err(|err| opml_error_in(err, if source == "-" { "stdin" } else { source }))?;
+    Ok(opml)
+}
+
+async fn read_opml_from(client: &FeedClient, source: &str) -> Result<Opml, Error> {
+    if is_opml_file(source) {
+        return read_opml(source);
+    }
+    let content = if source == "-" {
+        let mut content = Vec::new();
+        std::io::Read::read_to_end(&mut std::io::stdin(), &mut content).map_err(|err| {
+            Error::Io {
+                path: "stdin".into(),
+                source: err,
+            }
+        })?;
+        content
+    } else {
+        let response = fetch_feed(client, source).await?;
+        if !response.status.is_success() {
+            return Err(Error::HttpStatus {
+                url: redact_url(source).into_owned(),
+                status: response.status,
+            });
+        }
+        response.body
+    };
+    let source = redact_url(source);
+    let name = if source == "-" { "stdin" } else { &source };
+    parse_subscriptions(&content, name).map_err(|err| opml_error_in(err, name))
+}
+
+/// Parses OPML or, for `.txt` and `.list` files and documents not starting with
+/// a tag, a feed list
+fn parse_subscriptions(content: &[u8], name: &str) -> Result<Opml, Error> {
+    if is_feed_list_path(name) || looks_like_feed_list(content) {
+        return parse_feed_list(&String::from_utf8_lossy(content));
+    }
+    decode_xml(content).and_then(|content| parse_opml(&content))
+}
+
+/// Checks if file is named like a feed list, see [`parse_feed_list`]
+pub fn is_feed_list_path(file: &str) -> bool {
+    let lowercase = file.to_ascii_lowercase();
+    lowercase.ends_with(".txt") || lowercase.ends_with(".list")
+}
+
+/// Checks if the first line that isn't blank or a comment is something else than XML
+fn looks_like_feed_list(content: &[u8]) -> bool {
+    let content = content.strip_prefix(b"\xef\xbb\xbf").unwrap_or(content);
+    content
+        .split(|byte| *byte == b'\n')
+        .map(<[u8]>::trim_ascii)
+        .find(|line| !line.is_empty() && !line.starts_with(b"#"))
+        .is_none_or(|line| !line.starts_with(b"<"))
+}
+
+/// Reads list of feed URLs, one per line and optionally followed by a tab and the
+/// title of the feed. Blank lines and lines starting with `#` are skipped
+pub fn parse_feed_list(content: &str) -> Result<Opml, Error> {
+    let mut outlines = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (url, title) = match line.split_once('\t') {
+            Some((url, title)) => (url.trim(), Some(title.trim()).filter(|t| !t.is_empty())),
+            None => (line, None),
+        };
+        if let Err(err) = reqwest::Url::parse(url) {
+            return Err(Error::Opml {
+                path: None,
+                message: format!("line {}: invalid feed URL '{}': {}", number + 1, url, err),
+            });
+        }
+        outlines.push(Outline {
+            text: title.map(str::to_owned),
+            title: title.map(str::to_owned),
+            outline_type: Some("rss".to_owned()),
+            xml_url: Some(url.to_owned()),
+            html_url: None,
+            fdr_headers: None,
+            outline: Vec::new(),
+            unknown: Unknown::default(),
+        });
+    }
+    let mut opml = Opml::new("Feeds");
+    opml.body.outline = outlines;
+    Ok(opml)
+}
+
+/// Checks if OPML `source` of [`load_opml`] is a file, so it can be written back
+pub fn is_opml_file(source: &str) -> bool {
+    let lowercase = source.to_ascii_lowercase();
+    source != "-" && !lowercase.starts_with("http://") && !lowercase.starts_with("https://")
+}
+
+fn opml_error_in(err: Error, path: &str) -> Error {
+    match err {
+        Error::Opml { message, .. } | Error::Decode(message) => Error::Opml {
+            path: Some(path.into()),
+            message,
+        },
+        other => other,
+    }
+}
+
+pub fn parse_opml(content: &str) -> Result<Opml, Error> {
+    let invalid = |message| Error::Opml {
+        path: None,
+        message,
+    };
+    validate_opml(content).map_err(invalid)?;
+    let mut opml: Opml =
+        from_str(content).map_err(|err| invalid(format!("invalid OPML: {}", err)))?;
+    read_unknown(content, &mut opml).map_err(|err| invalid(format!("invalid OPML: {}", err)))?;
+    Ok(opml)
+}
+
+/// Collects what [`Opml`] has no fields for into its [`Unknown`] parts, moving
+/// comments inside `<body>` to the outline following them
+fn read_unknown(content: &str, opml: &mut Opml) -> Result<(), quick_xml::Error> {
+    let mut reader = quick_xml::Reader::from_str(content);
+    let raw_attributes = |element: &quick_xml::events::BytesStart, known: &[&[u8]]| {
+        element
+            .attributes()
+            .flatten()
+            .filter(|attribute| !known.contains(&attribute.key.as_ref()))
+            .map(|attribute| {
+                let name = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+                // values of single quoted attributes may have double quotes
+                let value = String::from_utf8_lossy(&attribute.value).replace('"', "&quot;");
+                (name, value)
+            })
+            .collect::<Vec<_>>()
+    };
+    // unknown parts of outlines in document order, and the open elements
+    let mut outlines: Vec<Unknown> = Vec::new();
+    let mut path: Vec<(Vec<u8>, Option<usize>)> = Vec::new();
+    let mut comments = Vec::new();
+    loop {
+        let start = reader.buffer_position();
+        let event = reader.read_event()?;
+        let parent = path.last().map(|(name, _)| name.as_slice());
+        let in_head = path.iter().any(|(name, _)| name == b"head");
+        match event {
+            Event::Comment(_) => {
+                let raw = content[start..reader.buffer_position()].to_owned();
+                if in_head {
+                    opml.head.extra.push(raw);
+                } else if path.iter().any(|(name, _)| name == b"body") {
+                    comments.push(raw);
+                } else {
+                    opml.unknown.comments.push(raw);
+                }
+            }
+            Event::Start(ref element) | Event::Empty(ref element)
+                if in_head && path.len() == 2 && element.local_name().as_ref() != b"title" =>
+            {
+                if matches!(event, Event::Start(_)) {
+                    reader.read_to_end(element.name())?;
+                }
+                let raw = content[start..reader.buffer_position()].to_owned();
+                opml.head.extra.push(raw);
+            }
+            Event::Start(ref element) | Event::Empty(ref element) => {
+                let name = element.local_name().as_ref().to_vec();
+                let mut index = None;
+                if path.is_empty() {
+                    opml.unknown.attributes = raw_attributes(element, &[b"version"]);
+                } else if name == b"outline" && matches!(parent, Some(b"body" | b"outline")) {
+                    index = Some(outlines.len());
+                    outlines.push(Unknown {
+                        attributes: raw_attributes(element, OUTLINE_ATTRIBUTES),
+                        comments: std::mem::take(&mut comments),
+                        trailing_comments: Vec::new(),
+                    });
+                }
+                if matches!(event, Event::Start(_)) {
+                    path.push((name, index));
+                }
+            }
+            Event::End(_) => match path.pop() {
+                Some((_, Some(index))) => {
+                    outlines[index].trailing_comments = std::mem::take(&mut comments);
+                }
+                Some((name, None)) if name == b"body" => {
+                    opml.body.trailing_comments = std::mem::take(&mut comments);
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    let mut outlines = outlines.into_iter();
+    assign_unknown(&mut opml.body.outline, &mut outlines);
+    Ok(())
+}
+
+/// Hands `unknown` parts to `outlines` and their children in document order
+fn assign_unknown(outlines: &mut [Outline], unknown: &mut impl Iterator<Item = Unknown>) {
+    for outline in outlines {
+        outline.unknown = unknown.next().unwrap_or_default();
+        assign_unknown(&mut outline.outline, unknown);
+    }
+}
+
+/// Checks OPML structure, as errors of deserializing are hard to act on
+fn validate_opml(content: &str) -> Result<(), String> {
+    let mut reader = quick_xml::Reader::from_str(content);
+    let line = |position: usize| content[..position.min(content.len())].matches('\n').count() + 1;
+    let mut path: Vec<Vec<u8>> = Vec::new();
+    let (mut root, mut head, mut title, mut body, mut outlines) = (false, false, false, false, 0);
+    loop {
+        let position = reader.buffer_position();
+        let event = reader
+            .read_event()
+            .map_err(|err| format!("line {}: invalid XML: {}", line(position), err))?;
+        let (element, empty) = match event {
+            Event::Start(element) => (element, false),
+            Event::Empty(element) => (element, true),
+            Event::End(_) => {
+                path.pop();
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+        let name = element.local_name().as_ref().to_vec();
+        match (path.len(), path.last().map(Vec::as_slice), name.as_slice()) {
+            (0, _, b"opml") => {
+                root = true;
+                if let Ok(None) = element.try_get_attribute("version") {
+                    return Err(format!(
+                        "line {}: missing required @version on <opml>",
+                        line(position)
+                    ));
+                }
+            }
+            (0, _, other) => {
+                return Err(format!(
+                    "not an OPML document, its root element is <{}>",
+                    String::from_utf8_lossy(other)
+                ))
+            }
+            (1, _, b"head") => head = true,
+            (1, _, b"body") => body = true,
+            (2, Some(b"head"), b"title") => title = true,
+            (_, Some(b"body" | b"outline"), b"outline") => {
+                outlines += 1;
+                let attribute = |key: &str| -> Result<Option<String>, String> {
+                    let attribute = element
+                        .try_get_attribute(key)
+                        .map_err(|err| format!("line {}: {}", line(position), err))?;
+                    Ok(attribute.map(|attribute| {
+                        decode_entities(&String::from_utf8_lossy(&attribute.value))
+                    }))
+                };
+                if attribute("type")?.as_deref() == Some("rss") && attribute("xmlUrl")?.is_none() {
+                    let name = attribute("title")?
+                        .or(attribute("text")?)
+                        .unwrap_or_default();
+                    return Err(format!(
+                        "line {}: missing required @xmlUrl on outline '{}'",
+                        line(position),
+                        name
+                    ));
+                }
+            }
+            _ => {}
+        }
+        if !empty {
+            path.push(name);
+        }
+    }
+    if !root {
+        if content.trim().is_empty() {
+            return Err("file is empty, expected an OPML document".to_owned());
+        }
+        return Err("not an OPML document, it has no root element".to_owned());
+    }
+    if let Some(name) = path.last() {
+        return Err(format!(
+            "line {}: document ends before <{}> is closed",
+            line(content.len()),
+            String::from_utf8_lossy(name)
+        ));
+    }
+    match (head, title, body) {
+        (false, _, _) => Err("missing <head> element".to_owned()),
+        (true, false, _) => Err("missing <title> element in <head>".to_owned()),
+        (_, _, false) => Err("missing <body> element".to_owned()),
+        _ if outlines == 0 => Err("<body> has no outlines, there are no feeds to read".to_owned()),
+        _ => Ok(()),
+    }
+}
+
+impl Opml {
+    /// Empty OPML 2.0 document titled `title`
+    pub fn new(title: &str) -> Self {
+        Opml {
+            version: "2.0".to_owned(),
+            head: Head {
+                title: title.to_owned(),
+                extra: Vec::new(),
+            },
+            body: BodyList {
+                outline: Vec::new(),
+                trailing_comments: Vec::new(),
+            },
+            unknown: Unknown::default(),
+        }
+    }
+
+    /// Serializes document to indented OPML XML, with the parts fdr doesn't read
+    /// as they were, so rewriting a file keeps e.g. `<ownerName>` and comments
+    pub fn to_xml(&self) -> Result<String, Error> {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        for comment in &self.unknown.comments {
+            xml.push_str(comment);
+            xml.push('\n');
+        }
+        xml.push_str(&format!("<opml version=\"{}\"", escape(&self.version)));
+        push_attributes(&mut xml, &self.unknown.attributes);
+        xml.push_str(">\n  <head>\n");
+        xml.push_str(&format!(
+            "    <title>{}</title>\n",
+            escape(&self.head.title)
+        ));
+        for extra in &self.head.extra {
+            xml.push_str(&format!("    {}\n", extra));
+        }
+        xml.push_str("  </head>\n  <body>\n");
+        for outline in &self.body.outline {
+            write_outline(&mut xml, outline, 2);
+        }
+        for comment in &self.body.trailing_comments {
+            xml.push_str(&format!("    {}\n", comment));
+        }
+        xml.push_str("  </body>\n</opml>\n");
+        Ok(xml)
+    }
+
+    /// Serializes feeds as [`parse_feed_list`] reads them, one `url<TAB>title` per line.
+    /// Categories are flattened
+    pub fn to_feed_list(&self) -> String {
+        get_rss_outlines(self)
+            .iter()
+            .map(|feed| match feed.outline.name() {
+                Some(name) => format!("{}\t{}\n", feed.xml_url, name.replace(['\t', '\n'], " ")),
+                None => format!("{}\n", feed.xml_url),
+            })
+            .collect()
+    }
+
+    /// Keeps only feeds `keep` returns true for. Categories left without outlines are removed
+    pub fn retain_feeds(&mut self, mut keep: impl FnMut(&Outline) -> bool) {
+        retain_outlines(&mut self.body.outline, &mut keep);
+    }
+
+    /// Removes feeds whose URL is a duplicate of an earlier one, see [`duplicate_feeds`].
+    /// Returns how many were removed
+    pub fn remove_duplicate_feeds(&mut self) -> usize {
+        let mut urls = HashSet::new();
+        let mut removed = 0;
+        self.retain_feeds(|outline| {
+            let keep = outline
+                .feed_url()
+                .is_none_or(|url| urls.insert(normalize_url(url)));
+            removed += usize::from(!keep);
+            keep
+        });
+        removed
+    }
+
+    /// Points feeds at `old` URL to `new` one, returns how many were changed
+    pub fn replace_feed_url(&mut self, old: &str, new: &str) -> usize {
+        replace_feed_url(&mut self.body.outline, old, new)
+    }
+}
+
+fn escape(text: &str) -> Cow<'_, str> {
+    quick_xml::escape::escape(text)
+}
+
+/// Appends ` name="value"` pairs of already escaped values
+fn push_attributes(xml: &mut String, attributes: &[(String, String)]) {
+    for (name, value) in attributes {
+        xml.push_str(&format!(" {}=\"{}\"", name, value));
+    }
+}
+
+/// Appends `outline` with its children, indented by `depth` levels of two spaces
+fn write_outline(xml: &mut String, outline: &Outline, depth: usize) {
+    let indent = "  ".repeat(depth);
+    for comment in &outline.unknown.comments {
+        xml.push_str(&format!("{}{}\n", indent, comment));
+    }
+    xml.push_str(&format!("{}<outline", indent));
+    let known = [
+        ("text", &outline.text),
+        ("title", &outline.title),
+        ("type", &outline.outline_type),
+        ("xmlUrl", &outline.xml_url),
+        ("htmlUrl", &outline.html_url),
+        ("fdrHeaders", &outline.fdr_headers),
+    ];
+    for (name, value) in known {
+        if let Some(value) = value {
+            xml.push_str(&format!(" {}=\"{}\"", name, escape(value)));
+        }
+    }
+    push_attributes(xml, &outline.unknown.attributes);
+    if outline.outline.is_empty() && outline.unknown.trailing_comments.is_empty() {
+        xml.push_str("/>\n");
+        return;
+    }
+    xml.push_str(">\n");
+    for child in &outline.outline {
+        write_outline(xml, child, depth + 1);
+    }
+    for comment in &outline.unknown.trailing_comments {
+        xml.push_str(&format!("{}  {}\n", indent, comment));
+    }
+    xml.push_str(&format!("{}</outline>\n", indent));
+}
+
+/// Combines `opmls` into one document titled like the first. Categories of the same
+/// name, ignoring case, are merged, and feeds subscribed in several documents are kept
+/// where they come first, with their title there. Outlines are sorted by name,
+/// categories before feeds
+pub fn merge_opmls(opmls: impl IntoIterator<Item = Opml>) -> Opml {
+    let mut opmls = opmls.into_iter();
+    let mut merged = opmls.next().unwrap_or_else(|| Opml::new("Feeds"));
+    let first = std::mem::take(&mut merged.body.outline);
+    let mut urls = HashSet::new();
+    for outlines in std::iter::once(first).chain(opmls.map(|opml| opml.body.outline)) {
+        merge_outlines(&mut merged.body.outline, outlines, &mut urls);
+    }
+    sort_outlines(&mut merged.body.outline);
+    merged
+}
+
+fn merge_outlines(target: &mut Vec<Outline>, outlines: Vec<Outline>, urls: &mut HashSet<String>) {
+    for mut outline in outlines {
+        if let Some(url) = outline.feed_url() {
+            if urls.insert(normalize_url(url)) {
+                target.push(outline);
+            }
+            continue;
+        }
+        if outline.outline.is_empty() {
+            target.push(outline);
+            continue;
+        }
+        let children = std::mem::take(&mut outline.outline);
+        let name = outline.category_name().to_lowercase();
+        let index = target
+            .iter()
+            .position(|other| {
+                other.feed_url().is_none()
+                    && !other.outline.is_empty()
+                    && other.category_name().to_lowercase() == name
+            })
+            .unwrap_or_else(|| {
+                target.push(outline);
+                target.len() - 1
+            });
+        merge_outlines(&mut target[index].outline, children, urls);
+        // categories with duplicates only
+        if target[index].outline.is_empty() {
+            target.remove(index);
+        }
+    }
+}
+
+fn sort_outlines(outlines: &mut [Outline]) {
+    outlines.sort_by_cached_key(|outline| {
+        (
+            outline.feed_url().is_some() || outline.outline.is_empty(),
+            outline.display_name().to_lowercase(),
+        )
+    });
+    for outline in outlines {
+        sort_outlines(&mut outline.outline);
+    }
+}
+
+fn replace_feed_url(outlines: &mut [Outline], old: &str, new: &str) -> usize {
+    let mut replaced = 0;
+    for outline in outlines {
+        if outline.feed_url() == Some(old) {
+            outline.xml_url = Some(new.to_owned());
+            replaced += 1;
+        }
+        replaced += replace_feed_url(&mut outline.outline, old, new);
+    }
+    replaced
+}
+
+fn retain_outlines(outlines: &mut Vec<Outline>, keep: &mut impl FnMut(&Outline) -> bool) {
+    outlines.retain_mut(|outline| {
+        if outline.outline.is_empty() {
+            return outline.feed_url().is_none() || keep(outline);
+        }
+        retain_outlines(&mut outline.outline, keep);
+        !outline.outline.is_empty()
+    });
+}
+
+/// Writes OPML to file. Feed lists, by name or by their current content, stay feed
+/// lists, though without their comments
+pub fn write_opml(opml: &Opml, file: &str) -> Result<(), Error> {
+    let is_list = is_feed_list_path(file)
+        || std::fs::read(file).is_ok_and(|content| looks_like_feed_list(&content));
+    let content = if is_list {
+        opml.to_feed_list()
+    } else {
+        opml.to_xml()?
+    };
+    std::fs::write(file, content).map_err(|source| Error::Io {
+        path: file.into(),
+        source,
+    })?;
+    Ok(())
+}
+
+impl FeedOutline<'_> {
+    /// Checks if feed is nested in category, see [`category_matches`]
+    pub fn in_category(&self, path: &str) -> bool {
+        category_matches(&self.category, path)
+    }
+}
+
+/// Checks if category path matches `/` separated `path`, e.g. `News/World`.
+/// The path may start at any nesting level and is compared ignoring case
+pub fn category_matches(category: &[&str], path: &str) -> bool {
+    let wanted: Vec<String> = path
+        .split('/')
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+    if wanted.is_empty() {
+        return true;
+    }
+    category.windows(wanted.len()).any(|names| {
+        names
+            .iter()
+            .zip(&wanted)
+            .all(|(name, wanted)| name.to_lowercase() == *wanted)
+    })
+}
+
+/// Lists paths of all categories containing feeds, e.g. `News` and `News/World`
+pub fn get_categories(opml: &Opml) -> Vec<String> {
+    let mut categories: Vec<String> = Vec::new();
+    for outline in get_rss_outlines(opml) {
+        for depth in 1..=outline.category.len() {
+            let path = outline.category[..depth].join("/");
+            if !categories.contains(&path) {
+                categories.push(path);
+            }
+        }
+    }
+    categories
+}
+
+/// Collects RSS outlines from the whole outline tree in document order
+pub fn get_rss_outlines(opml: &Opml) -> Vec<FeedOutline<'_>> {
+    let mut found = Vec::new();
+    collect_rss_outlines(&opml.body.outline, &mut Vec::new(), &mut found);
+    found
+}
+
+/// Groups feeds subscribed more than once, in document order. Feeds are the same when
+/// their URLs are equal after [`normalize_url`], e.g. `http://x.com/feed` and `https://X.com/feed/`
+pub fn duplicate_feeds(opml: &Opml) -> Vec<Vec<FeedOutline<'_>>> {
+    let mut groups: Vec<Vec<FeedOutline>> = Vec::new();
+    let mut group_of_url: HashMap<String, usize> = Default::default();
+    for outline in get_rss_outlines(opml) {
+        let url = normalize_url(outline.xml_url);
+        match group_of_url.get(&url) {
+            Some(&group) => groups[group].push(outline),
+            None => {
+                group_of_url.insert(url, groups.len());
+                groups.push(vec![outline]);
+            }
+        }
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+fn collect_rss_outlines<'a>(
+    outlines: &'a [Outline],
+    category: &mut Vec<&'a str>,
+    found: &mut Vec<FeedOutline<'a>>,
+) {
+    for outline in outlines {
+        if let Some(xml_url) = outline.feed_url() {
+            found.push(FeedOutline {
+                outline,
+                xml_url,
+                category: category.clone(),
+            });
+        }
+        if !outline.outline.is_empty() {
+            category.push(outline.category_name());
+            collect_rss_outlines(&outline.outline, category, found);
+            category.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_rss_outlines_walks_categories() {
+        let opml: Opml = from_str(
+            r#"<opml version="2.0"><head><title>t</title></head><body>
+                <outline text="Top" title="Top" type="rss" xmlUrl="http://top/rss"/>
+                <outline text="News" title="News">
+                    <outline text="World" title="World">
+                        <outline text="BBC" title="BBC" type="rss" xmlUrl="http://bbc/rss"/>
+                    </outline>
+                </outline>
+            </body></opml>"#,
+        )
+        .unwrap();
+        let outlines = get_rss_outlines(&opml);
+        let found: Vec<_> = outlines
+            .iter()
+            .map(|o| (o.xml_url, o.category.join("/")))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("http://top/rss", "".to_owned()),
+                ("http://bbc/rss", "News/World".to_owned())
+            ]
+        );
+        assert!(outlines[1].in_category("news/WORLD"));
+        assert!(outlines[1].in_category("World"));
+        assert!(!outlines[1].in_category("World/News"));
+        assert!(!outlines[0].in_category("News"));
+        assert_eq!(get_categories(&opml), ["News", "News/World"]);
+    }
+
+    #[test]
+    fn opml_round_trip() {
+        let opml: Opml = from_str(
+            r#"<opml version="2.0"><head><title>Feeds &amp; more</title></head><body>
+                <outline text="News">
+                    <outline text="BBC" title="BBC" type="rss" xmlUrl="http://bbc/rss?a=1&amp;b=2"/>
+                </outline>
+                <outline text="Top" type="rss" xmlUrl="http://top/rss" htmlUrl="http://top/"/>
+            </body></opml>"#,
+        )
+        .unwrap();
+        let xml = opml.to_xml().unwrap();
+        let reread: Opml = from_str(&xml).unwrap();
+        assert_eq!(reread.to_xml().unwrap(), xml);
+        assert_eq!(reread.head.title, "Feeds & more");
+        assert_eq!(
+            reread.body.outline[0].outline[0].xml_url.as_deref(),
+            Some("http://bbc/rss?a=1&b=2")
+        );
+        assert_eq!(
+            reread.body.outline[1].html_url.as_deref(),
+            Some("http://top/")
+        );
+    }
+
+    #[test]
+    fn opml_round_trip_keeps_unknown_parts() {
+        let source = r#"<?xml version="1.0"?>
+<!-- exported by Reader -->
+<opml version="1.1" xmlns:x="urn:x">
+  <head>
+    <title>Feeds</title>
+    <ownerName>Ann</ownerName>
+    <dateCreated>Mon, 01 Jan 2024 00:00:00 GMT</dateCreated>
+    <!-- about head -->
+  </head>
+  <body>
+    <!-- news first -->
+    <outline text="News" x:color='"red"'>
+      <outline text="BBC" type="rss" xmlUrl="http://bbc/rss" description="World &amp; UK" language="en"/>
+      <!-- end of news -->
+    </outline>
+    <outline text="Top" type="rss" xmlUrl="http://top/rss"/>
+    <!-- the end -->
+  </body>
+</opml>
+"#;
+        let mut opml = parse_opml(source).unwrap();
+        let bbc = &opml.body.outline[0].outline[0];
+        assert_eq!(
+            bbc.unknown.attributes,
+            [
+                ("description".to_owned(), "World &amp; UK".to_owned()),
+                ("language".to_owned(), "en".to_owned())
+            ]
+        );
+        let xml = opml.to_xml().unwrap();
+        assert_eq!(
+            xml,
+            source
+                .replace(
+                    r#"<?xml version="1.0"?>"#,
+                    r#"<?xml version="1.0" encoding="UTF-8"?>"#
+                )
+                .replace(r#"'"red"'"#, r#""&quot;red&quot;""#)
+        );
+        assert_eq!(parse_opml(&xml).unwrap().to_xml().unwrap(), xml);
+
+        // parts move along with their outlines
+        opml.retain_feeds(|outline| outline.xml_url.as_deref() != Some("http://bbc/rss"));
+        let xml = opml.to_xml().unwrap();
+        assert!(xml.contains("<ownerName>Ann</ownerName>") && xml.contains("<!-- the end -->"));
+        assert!(!xml.contains("description=") && !xml.contains("news first"));
+    }
+
+    #[test]
+    fn feed_list_round_trip() {
+        let list = "# my feeds\n\nhttps://a.example/feed\t Blog A \n  https://b.example/rss  \n";
+        let opml = parse_feed_list(list).unwrap();
+        let feeds: Vec<_> = get_rss_outlines(&opml)
+            .iter()
+            .map(|feed| (feed.xml_url, feed.outline.name()))
+            .collect();
+        assert_eq!(
+            feeds,
+            [
+                ("https://a.example/feed", Some("Blog A")),
+                ("https://b.example/rss", None)
+            ]
+        );
+        assert_eq!(
+            opml.to_feed_list(),
+            "https://a.example/feed\tBlog A\nhttps://b.example/rss\n"
+        );
+        assert!(looks_like_feed_list(
+            b"\xef\xbb\xbf# feeds\nhttps://a.example/feed"
+        ));
+        assert!(!looks_like_feed_list(b"\n  <?xml version=\"1.0\"?><opml/>"));
+        let err = parse_feed_list("https://a.example/feed\nfeed.xml\n").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("line 2: invalid feed URL 'feed.xml'"));
+    }
+
+    #[test]
+    fn outline_without_title() {
+        let opml: Opml = from_str(
+            r#"<opml version="1.0"><head><title>t</title></head><body>
+                <outline text="Only text" type="rss" xmlUrl="http://text/rss"/>
+                <outline type="rss" xmlUrl="https://example.com/feed.xml"/>
+            </body></opml>"#,
+        )
+        .unwrap();
+        let names: Vec<_> = opml
+            .body
+            .outline
+            .iter()
+            .map(Outline::display_name)
+            .collect();
+        assert_eq!(names, ["Only text", "example.com"]);
+    }
+
+    #[test]
+    fn duplicate_feeds_by_normalized_url() {
+        let mut opml: Opml = from_str(
+            r#"<opml version="2.0"><head><title>t</title></head><body>
+                <outline text="Blog" type="rss" xmlUrl="http://x.com/feed"/>
+                <outline text="Other" type="rss" xmlUrl="http://x.com/other"/>
+                <outline text="Tech">
+                    <outline text="Blog again" type="rss" xmlUrl="https://X.com/feed/"/>
+                </outline>
+            </body></opml>"#,
+        )
+        .unwrap();
+        let groups = duplicate_feeds(&opml);
+        let names: Vec<Vec<String>> = groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|feed| feed.outline.display_name())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(names, [["Blog", "Blog again"]]);
+        assert_eq!(groups[0][1].category, ["Tech"]);
+        assert_eq!(opml.remove_duplicate_feeds(), 1);
+        assert!(duplicate_feeds(&opml).is_empty());
+        assert_eq!(get_rss_outlines(&opml).len(), 2);
+    }
+
+    #[test]
+    fn merge_opmls_unions_categories() {
+        let first = parse_opml(
+            r#"<opml version="2.0"><head><title>Mine</title></head><body>
+                <outline text="News">
+                    <outline text="BBC" type="rss" xmlUrl="https://bbc.example/rss"/>
+                </outline>
+                <outline text="Zed" type="rss" xmlUrl="https://zed.example/feed"/>
+            </body></opml>"#,
+        )
+        .unwrap();
+        let second = parse_opml(
+            r#"<opml version="2.0"><head><title>Theirs</title></head><body>
+                <outline text="Blogs">
+                    <outline text="Zed blog" type="rss" xmlUrl="http://ZED.example/feed/"/>
+                </outline>
+                <outline text="news">
+                    <outline text="BBC News" type="rss" xmlUrl="https://bbc.example/rss"/>
+                    <outline text="AP" type="rss" xmlUrl="https://ap.example/rss"/>
+                </outline>
+                <outline text="Alpha" type="rss" xmlUrl="https://alpha.example/feed"/>
+            </body></opml>"#,
+        )
+        .unwrap();
+        let merged = merge_opmls([first, second]);
+        assert_eq!(merged.head.title, "Mine");
+        let found: Vec<_> = get_rss_outlines(&merged)
+            .iter()
+            .map(|o| (o.category.join("/"), o.outline.display_name()))
+            .collect();
+        let expected = [("News", "AP"), ("News", "BBC"), ("", "Alpha"), ("", "Zed")];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(category, name)| (category.to_string(), name.to_string()))
+            .collect();
+        assert_eq!(found, expected);
+        // the category left with duplicates only is dropped
+        assert_eq!(get_categories(&merged), ["News"]);
+    }
+
+    #[test]
+    fn parse_opml_errors() {
+        let error = |text| parse_opml(text).unwrap_err().to_string();
+        assert_eq!(error(" \n"), "file is empty, expected an OPML document");
+        assert_eq!(
+            error("<html><body/></html>"),
+            "not an OPML document, its root element is <html>"
+        );
+        assert_eq!(
+            error("<opml version=\"2.0\"><body/></opml>"),
+            "missing <head> element"
+        );
+        assert_eq!(
+            error("<opml version=\"2.0\"><head><title>t</title></head><body/></opml>"),
+            "<body> has no outlines, there are no feeds to read"
+        );
+        assert_eq!(
+            error(
+                "<opml version=\"2.0\"><head><title>t</title></head><body>\n\
+                 <outline text=\"Tech\">\n<outline text=\"Blog\" type=\"rss\"/>\n\
+                 </outline></body></opml>"
+            ),
+            "line 3: missing required @xmlUrl on outline 'Blog'"
+        );
+        assert_eq!(
+            error("<opml version=\"2.0\"><head><title>t</title></head><body>\n<outline>"),
+            "line 2: document ends before <outline> is closed"
+        );
+    }
+}
@@ -0,0 +1,420 @@
+//! Regular expressions of `--filter-regex`, matched by backtracking. Supports
+//! literals, `.`, classes like `[a-z]` and `\d`, `\w`, `\s`, anchors `^`, `$` and
+//! `\b`, groups, `|`, the quantifiers `*`, `+`, `?` and `{n,m}`, and a leading `(?i)`
+use std::fmt;
+
+/// Compiled regular expression
+#[derive(Clone)]
+pub struct Regex {
+    pattern: String,
+    root: Node,
+    ignore_case: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class {
+        items: Vec<ClassItem>,
+        negated: bool,
+    },
+    Start,
+    End,
+    /// `\b`, or `\B` when `false`
+    WordBoundary(bool),
+    Concat(Vec<Node>),
+    Alternation(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    /// `\d`, `\w` or `\s`, negated for `\D`, `\W` and `\S`
+    Shorthand(char, bool),
+}
+
+/// Longest `{n,m}` repetition accepted
+const MAX_REPEAT: usize = 1000;
+
+impl Regex {
+    /// Compiles `pattern`, failing with a message naming what's wrong
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let (ignore_case, rest) = match pattern.strip_prefix("(?i)") {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let mut parser = Parser {
+            chars: rest.chars().collect(),
+            pos: 0,
+        };
+        let root = parser
+            .alternation()
+            .and_then(|root| match parser.peek() {
+                Some(')') => Err("unmatched ')'".to_owned()),
+                _ => Ok(root),
+            })
+            .map_err(|reason| format!("Invalid regex '{}': {}", pattern, reason))?;
+        Ok(Regex {
+            pattern: pattern.to_owned(),
+            root,
+            ignore_case,
+        })
+    }
+
+    /// Checks if the expression matches anywhere in `text`
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let matcher = Matcher {
+            text: &text,
+            ignore_case: self.ignore_case,
+        };
+        (0..=text.len()).any(|start| matcher.node(&self.root, start, &mut |_| true))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+}
+
+impl fmt::Debug for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Regex").field(&self.pattern).finish()
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn alternation(&mut self) -> Result<Node, String> {
+        let mut branches = vec![self.concat()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            branches.push(self.concat()?);
+        }
+        Ok(match branches.len() {
+            1 => branches.remove(0),
+            _ => Node::Alternation(branches),
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek().filter(|&c| c != '|' && c != ')') {
+            self.pos += 1;
+            let atom = match c {
+                '(' => {
+                    if self.chars[self.pos..].starts_with(&['?', ':']) {
+                        self.pos += 2;
+                    } else if self.peek() == Some('?') {
+                        return Err("only (?:...) groups and a leading (?i) are supported".into());
+                    }
+                    let inner = self.alternation()?;
+                    if self.next() != Some(')') {
+                        return Err("unclosed group".to_owned());
+                    }
+                    inner
+                }
+                '[' => self.class()?,
+                '\\' => self.escape()?,
+                '.' => Node::Any,
+                '^' => Node::Start,
+                '$' => Node::End,
+                '*' | '+' | '?' => return Err(format!("nothing to repeat before '{}'", c)),
+                c => Node::Char(c),
+            };
+            nodes.push(self.quantifier(atom)?);
+        }
+        Ok(match nodes.len() {
+            1 => nodes.remove(0),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn quantifier(&mut self, node: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => match self.repetition()? {
+                Some(bounds) => bounds,
+                // `{` not starting a repetition is a literal
+                None => return Ok(node),
+            },
+            _ => return Ok(node),
+        };
+        if matches!(node, Node::Start | Node::End | Node::WordBoundary(_)) {
+            return Err("nothing to repeat after an anchor".to_owned());
+        }
+        // skips the quantifier, or the closing brace of a repetition
+        self.pos += 1;
+        // laziness doesn't change whether there's a match
+        if self.peek() == Some('?') {
+            self.pos += 1;
+        }
+        Ok(Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+        })
+    }
+
+    /// Parses `{n}`, `{n,}` or `{n,m}`, leaving the closing brace to be skipped
+    fn repetition(&mut self) -> Result<Option<(usize, Option<usize>)>, String> {
+        let rest: String = self.chars[self.pos + 1..].iter().collect();
+        let Some(end) = rest.find('}') else {
+            return Ok(None);
+        };
+        let bounds = &rest[..end];
+        let number = |text: &str| text.trim().parse::<usize>().ok();
+        let (min, max) = match bounds.split_once(',') {
+            None => match number(bounds) {
+                Some(n) => (n, Some(n)),
+                None => return Ok(None),
+            },
+            Some((min, "")) => match number(min) {
+                Some(min) => (min, None),
+                None => return Ok(None),
+            },
+            Some((min, max)) => match (number(min), number(max)) {
+                (Some(min), Some(max)) => (min, Some(max)),
+                _ => return Ok(None),
+            },
+        };
+        if max.is_some_and(|max| max < min) {
+            return Err(format!("invalid repetition {{{}}}", bounds));
+        }
+        if min.max(max.unwrap_or_default()) > MAX_REPEAT {
+            return Err(format!("repetition {{{}}} is too long", bounds));
+        }
+        self.pos += bounds.chars().count() + 1;
+        Ok(Some((min, max)))
+    }
+
+    fn escape(&mut self) -> Result<Node, String> {
+        match self.next() {
+            None => Err("trailing backslash".to_owned()),
+            Some('b') => Ok(Node::WordBoundary(true)),
+            Some('B') => Ok(Node::WordBoundary(false)),
+            Some(c) => match self.escaped_class_item(c)? {
+                ClassItem::Range(c, _) => Ok(Node::Char(c)),
+                item => Ok(Node::Class {
+                    items: vec![item],
+                    negated: false,
+                }),
+            },
+        }
+    }
+
+    /// Character or shorthand class escaped by a backslash
+    fn escaped_class_item(&self, c: char) -> Result<ClassItem, String> {
+        let literal = |c| Ok(ClassItem::Range(c, c));
+        match c {
+            'd' | 'w' | 's' => Ok(ClassItem::Shorthand(c, false)),
+            'D' | 'W' | 'S' => Ok(ClassItem::Shorthand(c.to_ascii_lowercase(), true)),
+            'n' => literal('\n'),
+            't' => literal('\t'),
+            c if c.is_alphanumeric() => Err(format!("unknown escape \\{}", c)),
+            c => literal(c),
+        }
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut items = Vec::new();
+        loop {
+            let c = self.next().ok_or("unclosed character class")?;
+            let item = match c {
+                // `]` first in the class is a literal
+                ']' if !items.is_empty() => break,
+                '\\' => {
+                    let c = self.next().ok_or("unclosed character class")?;
+                    self.escaped_class_item(c)?
+                }
+                c => ClassItem::Range(c, c),
+            };
+            let is_range = self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']');
+            match item {
+                ClassItem::Range(start, _) if is_range => {
+                    self.pos += 1;
+                    let end = match self.next().ok_or("unclosed character class")? {
+                        '\\' => match self.escaped_class_item(self.peek().unwrap_or('\\'))? {
+                            ClassItem::Range(end, _) => {
+                                self.pos += 1;
+                                end
+                            }
+                            _ => return Err("invalid range in character class".to_owned()),
+                        },
+                        end => end,
+                    };
+                    if end < start {
+                        return Err(format!("invalid range {}-{}", start, end));
+                    }
+                    items.push(ClassItem::Range(start, end));
+                }
+                item => items.push(item),
+            }
+        }
+        Ok(Node::Class { items, negated })
+    }
+}
+
+struct Matcher<'a> {
+    text: &'a [char],
+    ignore_case: bool,
+}
+
+impl Matcher<'_> {
+    /// Matches `node` at `pos`, calling `next` with every position a match ends at
+    /// until it accepts one
+    fn node(&self, node: &Node, pos: usize, next: &mut dyn FnMut(usize) -> bool) -> bool {
+        let current = self.text.get(pos).copied();
+        match node {
+            Node::Char(expected) => {
+                current.is_some_and(|c| self.same_char(c, *expected)) && next(pos + 1)
+            }
+            Node::Any => current.is_some_and(|c| c != '\n') && next(pos + 1),
+            Node::Class { items, negated } => {
+                current.is_some_and(|c| self.in_class(c, items) != *negated) && next(pos + 1)
+            }
+            Node::Start => pos == 0 && next(pos),
+            Node::End => pos == self.text.len() && next(pos),
+            Node::WordBoundary(expected) => {
+                let before = pos > 0 && is_word_char(self.text[pos - 1]);
+                let after = current.is_some_and(is_word_char);
+                (before != after) == *expected && next(pos)
+            }
+            Node::Concat(nodes) => self.sequence(nodes, pos, next),
+            Node::Alternation(branches) => branches
+                .iter()
+                .any(|branch| self.node(branch, pos, &mut *next)),
+            Node::Repeat { node, min, max } => self.repeat(node, *min, *max, 0, pos, next),
+        }
+    }
+
+    fn sequence(&self, nodes: &[Node], pos: usize, next: &mut dyn FnMut(usize) -> bool) -> bool {
+        match nodes {
+            [] => next(pos),
+            [first, rest @ ..] => self.node(first, pos, &mut |end| self.sequence(rest, end, next)),
+        }
+    }
+
+    /// Matches greedily, as many repetitions as possible first
+    fn repeat(
+        &self,
+        node: &Node,
+        min: usize,
+        max: Option<usize>,
+        count: usize,
+        pos: usize,
+        next: &mut dyn FnMut(usize) -> bool,
+    ) -> bool {
+        if max.is_some_and(|max| count >= max) {
+            return next(pos);
+        }
+        let more = self.node(node, pos, &mut |end| {
+            // an empty match would repeat forever, further ones can't match more
+            if end == pos {
+                return next(end);
+            }
+            self.repeat(node, min, max, count + 1, end, next)
+        });
+        more || count >= min && next(pos)
+    }
+
+    fn same_char(&self, c: char, expected: char) -> bool {
+        c == expected || self.ignore_case && c.to_lowercase().eq(expected.to_lowercase())
+    }
+
+    fn in_class(&self, c: char, items: &[ClassItem]) -> bool {
+        let in_items = |c: char| {
+            items.iter().any(|item| match *item {
+                ClassItem::Range(start, end) => (start..=end).contains(&c),
+                ClassItem::Shorthand(kind, negated) => {
+                    let matches = match kind {
+                        'd' => c.is_ascii_digit(),
+                        'w' => is_word_char(c),
+                        _ => c.is_whitespace(),
+                    };
+                    matches != negated
+                }
+            })
+        };
+        in_items(c)
+            || self.ignore_case
+                && (c.to_lowercase().any(in_items) || c.to_uppercase().any(in_items))
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regex_matching() {
+        let is_match = |pattern, text| Regex::new(pattern).unwrap().is_match(text);
+        assert!(is_match(r"\brust\b", "Rust and rust"));
+        assert!(!is_match(r"\brust\b", "rustup 1.27 released"));
+        assert!(is_match(r"(?i)^RUST\s+\d+\.\d+", "Rust 1.80 is out"));
+        assert!(!is_match(r"^rust", "Why rust"));
+        assert!(is_match(r"colou?r", "color"));
+        assert!(is_match(r"(?:ab|cd){2}$", "xxabcd"));
+        assert!(!is_match(r"^(?:ab|cd){3}$", "abcd"));
+        assert!(is_match(r"[^a-z ]", "lower UPPER"));
+        assert!(!is_match(r"[^a-z ]", "lower only"));
+        assert!(is_match(r"a{2,3}b", "caab"));
+        assert!(!is_match(r"^a{2,3}b", "aaaab"));
+        assert!(is_match(r"x{", "x{"));
+        assert!(is_match(r"(a*)*b", "aaab"));
+        assert!(is_match(r"[\w-]+\.rs", "my-crate.rs"));
+        assert!(is_match(r"(?i)[a-c]", "B"));
+        assert!(is_match("", "anything"));
+    }
+
+    #[test]
+    fn regex_errors() {
+        let error = |pattern| Regex::new(pattern).unwrap_err();
+        assert_eq!(error("(rust"), "Invalid regex '(rust': unclosed group");
+        assert_eq!(error("rust)"), "Invalid regex 'rust)': unmatched ')'");
+        assert_eq!(
+            error("*a"),
+            "Invalid regex '*a': nothing to repeat before '*'"
+        );
+        assert_eq!(
+            error("[a-"),
+            "Invalid regex '[a-': unclosed character class"
+        );
+        assert_eq!(error("[z-a]"), "Invalid regex '[z-a]': invalid range z-a");
+        assert_eq!(error(r"\q"), r"Invalid regex '\q': unknown escape \q");
+        assert_eq!(
+            error("a{3,2}"),
+            "Invalid regex 'a{3,2}': invalid repetition {3,2}"
+        );
+        assert!(error("(?=a)").contains("only (?:...) groups"));
+    }
+}
@@ -0,0 +1,506 @@
+//! Dates of items, and durations and date bounds given on the command line
+use crate::Error;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeDelta};
+use std::str::FromStr;
+
+/// Parses item dates. Besides RFC 2822 (RSS) and RFC 3339 (Atom, Dublin Core)
+/// accepts their common non-compliant variants, dates without time zone are UTC
+pub fn parse_date(raw: &str) -> Result<DateTime<FixedOffset>, Error> {
+    let raw = raw.trim();
+    let (date, format) = parse_date_format(raw).ok_or_else(|| Error::DateParse(raw.to_owned()))?;
+    log::debug!("Parsed date '{}' as {}", raw, format);
+    Ok(date)
+}
+
+/// Returns parsed date with the name of format that matched
+fn parse_date_format(raw: &str) -> Option<(DateTime<FixedOffset>, &'static str)> {
+    if let Ok(date) = DateTime::parse_from_rfc2822(raw) {
+        return Some((date, "RFC 2822"));
+    }
+    if let Ok(date) = DateTime::from_str(raw) {
+        return Some((date, "RFC 3339"));
+    }
+    let lenient = lenient_rfc2822(raw);
+    if let Ok(date) = DateTime::parse_from_rfc2822(&lenient) {
+        return Some((date, "lenient RFC 2822"));
+    }
+    const WITH_OFFSET: &[&str] = &["%Y-%m-%dT%H:%M:%S%.f%z", "%Y-%m-%d %H:%M:%S%.f %z"];
+    for format in WITH_OFFSET {
+        if let Ok(date) = DateTime::parse_from_str(raw, format) {
+            return Some((date, format));
+        }
+    }
+    const WITHOUT_OFFSET: &[&str] = &[
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+        "%d %b %Y %H:%M:%S",
+        "%d %b %Y %H:%M",
+    ];
+    for format in WITHOUT_OFFSET {
+        let value = if format.contains("%b") { &lenient } else { raw };
+        if let Ok(date) = NaiveDateTime::parse_from_str(value, format) {
+            return Some((date.and_utc().fixed_offset(), format));
+        }
+    }
+    let date = NaiveDate::parse_from_str(raw, "%Y-%m-%d").ok()?;
+    Some((
+        date.and_hms_opt(0, 0, 0)?.and_utc().fixed_offset(),
+        "%Y-%m-%d",
+    ))
+}
+
+/// Time zone abbreviations seen in feeds that RFC 2822 doesn't define
+const ZONE_OFFSETS: &[(&str, &str)] = &[
+    ("UTC", "+0000"),
+    ("WET", "+0000"),
+    ("BST", "+0100"),
+    ("CET", "+0100"),
+    ("WEST", "+0100"),
+    ("CEST", "+0200"),
+    ("EET", "+0200"),
+    ("EEST", "+0300"),
+    ("MSK", "+0300"),
+    ("IST", "+0530"),
+    ("SGT", "+0800"),
+    ("HKT", "+0800"),
+    ("JST", "+0900"),
+    ("KST", "+0900"),
+    ("AEST", "+1000"),
+    ("AEDT", "+1100"),
+    ("NZST", "+1200"),
+    ("NZDT", "+1300"),
+];
+
+/// Rewrites RFC 2822-like date into the strict form: drops the weekday (often wrong
+/// or spelled out), shortens full month names and replaces unknown zone names
+fn lenient_rfc2822(raw: &str) -> String {
+    let raw = match raw.split_once(',') {
+        Some((weekday, rest)) if weekday.chars().all(char::is_alphabetic) => rest,
+        _ => raw,
+    };
+    raw.split_whitespace()
+        .map(|word| {
+            if let Some((_, offset)) = ZONE_OFFSETS.iter().find(|(zone, _)| *zone == word) {
+                return offset.to_string();
+            }
+            if word.len() > 3 && word.chars().all(char::is_alphabetic) {
+                word.chars().take(3).collect()
+            } else {
+                word.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Longest duration [`parse_duration`] accepts, 100000 years
+const MAX_DURATION_DAYS: i64 = 100_000 * 365;
+
+/// Parses durations like `500ms`, `90s`, `15m`, `48h`, `3d` or `2w`
+pub fn parse_duration(value: &str) -> Result<TimeDelta, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .map_err(|_| format!("Invalid duration '{}', expected e.g. 15m, 6h or 3d", value))?;
+    let delta = match unit {
+        "ms" => TimeDelta::try_milliseconds(amount),
+        "s" => TimeDelta::try_seconds(amount),
+        "m" => TimeDelta::try_minutes(amount),
+        "h" => TimeDelta::try_hours(amount),
+        "d" => TimeDelta::try_days(amount),
+        "w" => TimeDelta::try_weeks(amount),
+        _ => {
+            return Err(format!(
+                "Invalid duration unit in '{}', expected one of ms, s, m, h, d, w",
+                value
+            ))
+        }
+    };
+    // dates this far back or ahead of now are still representable
+    delta
+        .filter(|delta| delta.num_days() <= MAX_DURATION_DAYS)
+        .ok_or_else(|| format!("Duration '{}' out of range", value))
+}
+
+/// Formats duration in the largest unit [`parse_duration`] reads it back exactly in,
+/// e.g. `500ms`, `90s` or `2h`
+pub fn format_duration(duration: TimeDelta) -> String {
+    let millis = duration.num_milliseconds();
+    let units = [
+        ("w", 7 * 24 * 3600 * 1000),
+        ("d", 24 * 3600 * 1000),
+        ("h", 3600 * 1000),
+        ("m", 60 * 1000),
+        ("s", 1000),
+    ];
+    for (unit, unit_millis) in units {
+        if millis != 0 && millis % unit_millis == 0 {
+            return format!("{}{}", millis / unit_millis, unit);
+        }
+    }
+    format!("{}ms", millis)
+}
+
+/// Point in time given on the command line, absolute or relative to now
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateBound {
+    /// `3d`, `48h`, ... before now
+    Ago(TimeDelta),
+    /// Midnight of a day in the local timezone, e.g. `2024-01-15`
+    Day(NaiveDate),
+    /// Full date with time, in any format `parse_date` accepts
+    At(DateTime<FixedOffset>),
+}
+
+impl DateBound {
+    pub fn resolve(&self, now: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match self {
+            DateBound::Ago(delta) => now - *delta,
+            DateBound::Day(day) => day
+                .and_hms_opt(0, 0, 0)
+                .unwrap_or_default()
+                .and_local_timezone(*now.offset())
+                .single()
+                .unwrap_or(now),
+            DateBound::At(date) => *date,
+        }
+    }
+}
+
+/// Parses `--since`/`--until` values: `3d`, `2024-01-15` or a full date
+pub fn parse_date_bound(value: &str) -> Result<DateBound, String> {
+    let value = value.trim();
+    if let Ok(delta) = parse_duration(value) {
+        return Ok(DateBound::Ago(delta));
+    }
+    if let Ok(day) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(DateBound::Day(day));
+    }
+    parse_date(value).map(DateBound::At).map_err(|_| {
+        format!(
+            "Invalid date '{}', expected e.g. 2024-01-15, 2024-01-15T08:00:00Z or 3d",
+            value
+        )
+    })
+}
+
+/// Language of relative dates, see [`date_diff`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+    De,
+    Fr,
+}
+
+#[derive(Clone, Copy)]
+enum Unit {
+    Year,
+    Month,
+    Week,
+    Day,
+    Hour,
+    Minute,
+}
+
+impl Locale {
+    /// Parses language tag like `de`, `es-MX` or `fr_FR.UTF-8`. `C` and `POSIX` are English
+    pub fn lookup(tag: &str) -> Result<Locale, String> {
+        let language = tag
+            .split(['_', '-', '.', '@'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match language.as_str() {
+            "en" | "c" | "posix" => Ok(Locale::En),
+            "es" => Ok(Locale::Es),
+            "de" => Ok(Locale::De),
+            "fr" => Ok(Locale::Fr),
+            _ => Err(format!(
+                "Unsupported locale '{}', expected one of en, es, de, fr",
+                tag
+            )),
+        }
+    }
+
+    /// Language code, as [`Locale::lookup`] takes it
+    pub fn code(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Es => "es",
+            Locale::De => "de",
+            Locale::Fr => "fr",
+        }
+    }
+
+    /// Locale of `LC_ALL`, `LC_MESSAGES` or `LANG`, the first one set.
+    /// Unsupported languages fall back to English
+    pub fn from_env() -> Locale {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Locale::lookup(&value).ok())
+            .unwrap_or_default()
+    }
+
+    /// Singular and plural of `unit`, in the case following [`Locale::ago`]
+    fn unit_names(self, unit: Unit) -> (&'static str, &'static str) {
+        match (self, unit) {
+            (Locale::En, Unit::Year) => ("year", "years"),
+            (Locale::En, Unit::Month) => ("month", "months"),
+            (Locale::En, Unit::Week) => ("week", "weeks"),
+            (Locale::En, Unit::Day) => ("day", "days"),
+            (Locale::En, Unit::Hour) => ("hour", "hours"),
+            (Locale::En, Unit::Minute) => ("minute", "minutes"),
+            (Locale::Es, Unit::Year) => ("año", "años"),
+            (Locale::Es, Unit::Month) => ("mes", "meses"),
+            (Locale::Es, Unit::Week) => ("semana", "semanas"),
+            (Locale::Es, Unit::Day) => ("día", "días"),
+            (Locale::Es, Unit::Hour) => ("hora", "horas"),
+            (Locale::Es, Unit::Minute) => ("minuto", "minutos"),
+            // dative, as after "vor"
+            (Locale::De, Unit::Year) => ("Jahr", "Jahren"),
+            (Locale::De, Unit::Month) => ("Monat", "Monaten"),
+            (Locale::De, Unit::Week) => ("Woche", "Wochen"),
+            (Locale::De, Unit::Day) => ("Tag", "Tagen"),
+            (Locale::De, Unit::Hour) => ("Stunde", "Stunden"),
+            (Locale::De, Unit::Minute) => ("Minute", "Minuten"),
+            (Locale::Fr, Unit::Year) => ("an", "ans"),
+            (Locale::Fr, Unit::Month) => ("mois", "mois"),
+            (Locale::Fr, Unit::Week) => ("semaine", "semaines"),
+            (Locale::Fr, Unit::Day) => ("jour", "jours"),
+            (Locale::Fr, Unit::Hour) => ("heure", "heures"),
+            (Locale::Fr, Unit::Minute) => ("minute", "minutes"),
+        }
+    }
+
+    /// Formats `count` units in the past, picking the plural form by the rules
+    /// of the language
+    fn ago(self, count: i64, unit: Unit) -> String {
+        let (singular, plural) = self.unit_names(unit);
+        // French counts 0 and 1 as singular, the others only 1
+        let is_singular = match self {
+            Locale::Fr => count <= 1,
+            _ => count == 1,
+        };
+        let name = if is_singular { singular } else { plural };
+        match self {
+            // English says just "day ago" for a single unit
+            Locale::En if is_singular => format!("{} ago", name),
+            Locale::En => format!("{} {} ago", count, name),
+            Locale::Es => format!("hace {} {}", count, name),
+            Locale::De => format!("vor {} {}", count, name),
+            Locale::Fr => format!("il y a {} {}", count, name),
+        }
+    }
+
+    fn just_now(self) -> &'static str {
+        match self {
+            Locale::En => "just now",
+            Locale::Es => "ahora mismo",
+            Locale::De => "gerade eben",
+            Locale::Fr => "à l'instant",
+        }
+    }
+
+    /// Words for the calendar day of now and the one before
+    fn today_yesterday(self) -> (&'static str, &'static str) {
+        match self {
+            Locale::En => ("today", "yesterday"),
+            Locale::Es => ("hoy", "ayer"),
+            Locale::De => ("heute", "gestern"),
+            Locale::Fr => ("aujourd'hui", "hier"),
+        }
+    }
+}
+
+/// Converts time delta to human friendly string in the language of `locale`,
+/// e.g. "just now", "day ago", "3 days ago", "hace 3 días"
+pub fn date_diff(delta: TimeDelta, locale: Locale) -> String {
+    let days = delta.num_days();
+    if days >= 365 {
+        locale.ago(days / 365, Unit::Year)
+    } else if days >= 30 {
+        locale.ago(days / 30, Unit::Month)
+    } else if delta.num_weeks() >= 1 {
+        locale.ago(delta.num_weeks(), Unit::Week)
+    } else if days >= 1 {
+        locale.ago(days, Unit::Day)
+    } else if delta.num_hours() >= 1 {
+        locale.ago(delta.num_hours(), Unit::Hour)
+    } else if delta.num_minutes() >= 1 {
+        locale.ago(delta.num_minutes(), Unit::Minute)
+    } else {
+        locale.just_now().to_owned()
+    }
+}
+
+/// Like [`date_diff`], but dates of the calendar day of `now` are `today, 14:05` and
+/// of the day before `yesterday, 14:05`, calendar days being those of `now`'s zone
+pub fn calendar_date_diff(
+    date: DateTime<FixedOffset>,
+    now: DateTime<FixedOffset>,
+    locale: Locale,
+) -> String {
+    let date = date.with_timezone(now.offset());
+    let days = now.date_naive() - date.date_naive();
+    let (today, yesterday) = locale.today_yesterday();
+    match days.num_days() {
+        0 => format!("{}, {}", today, date.format("%H:%M")),
+        1 => format!("{}, {}", yesterday, date.format("%H:%M")),
+        _ => date_diff(now - date, locale),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn date_diff_units() {
+        assert_eq!(date_diff(TimeDelta::seconds(30), Locale::En), "just now");
+        assert_eq!(date_diff(TimeDelta::days(1), Locale::En), "day ago");
+        assert_eq!(date_diff(TimeDelta::days(2), Locale::En), "2 days ago");
+        assert_eq!(date_diff(TimeDelta::weeks(1), Locale::En), "week ago");
+        assert_eq!(date_diff(TimeDelta::weeks(4), Locale::En), "4 weeks ago");
+        assert_eq!(date_diff(TimeDelta::weeks(5), Locale::En), "month ago");
+        assert_eq!(date_diff(TimeDelta::days(30), Locale::En), "month ago");
+        assert_eq!(date_diff(TimeDelta::days(45), Locale::En), "month ago");
+        assert_eq!(date_diff(TimeDelta::days(13 * 30), Locale::En), "year ago");
+        assert_eq!(date_diff(TimeDelta::days(365), Locale::En), "year ago");
+        assert_eq!(date_diff(TimeDelta::days(400), Locale::En), "year ago");
+        assert_eq!(
+            date_diff(TimeDelta::days(3 * 365), Locale::En),
+            "3 years ago"
+        );
+    }
+
+    #[test]
+    fn date_diff_locales() {
+        assert_eq!(Locale::lookup("es_MX.UTF-8"), Ok(Locale::Es));
+        assert_eq!(Locale::lookup("de-AT"), Ok(Locale::De));
+        assert_eq!(Locale::lookup("C"), Ok(Locale::En));
+        assert!(Locale::lookup("pt_BR").is_err());
+        assert_eq!(date_diff(TimeDelta::days(3), Locale::Es), "hace 3 días");
+        assert_eq!(date_diff(TimeDelta::hours(1), Locale::Es), "hace 1 hora");
+        assert_eq!(date_diff(TimeDelta::days(1), Locale::De), "vor 1 Tag");
+        assert_eq!(date_diff(TimeDelta::days(800), Locale::De), "vor 2 Jahren");
+        assert_eq!(
+            date_diff(TimeDelta::weeks(2), Locale::Fr),
+            "il y a 2 semaines"
+        );
+        assert_eq!(date_diff(TimeDelta::days(60), Locale::Fr), "il y a 2 mois");
+        assert_eq!(date_diff(TimeDelta::seconds(5), Locale::Fr), "à l'instant");
+        let date = |text| DateTime::parse_from_rfc3339(text).unwrap();
+        assert_eq!(
+            calendar_date_diff(
+                date("2024-05-01T15:00:00+02:00"),
+                date("2024-05-02T09:00:00+02:00"),
+                Locale::De
+            ),
+            "gestern, 15:00"
+        );
+    }
+
+    #[test]
+    fn calendar_date_diff_days() {
+        let date = |text| DateTime::parse_from_rfc3339(text).unwrap();
+        let now = date("2024-05-02T09:00:00+02:00");
+        assert_eq!(
+            calendar_date_diff(date("2024-05-02T00:30:00+02:00"), now, Locale::En),
+            "today, 00:30"
+        );
+        // 18 hours ago, but on the day before
+        assert_eq!(
+            calendar_date_diff(date("2024-05-01T15:00:00+02:00"), now, Locale::En),
+            "yesterday, 15:00"
+        );
+        // the calendar day is the one of `now`'s zone
+        assert_eq!(
+            calendar_date_diff(date("2024-04-30T23:30:00+00:00"), now, Locale::En),
+            "yesterday, 01:30"
+        );
+        assert_eq!(
+            calendar_date_diff(date("2024-04-30T20:00:00+02:00"), now, Locale::En),
+            "day ago"
+        );
+        assert_eq!(
+            calendar_date_diff(date("2024-04-25T09:00:00+02:00"), now, Locale::En),
+            "week ago"
+        );
+    }
+
+    #[test]
+    fn parse_date_variants() {
+        let parsed = |raw| parse_date(raw).unwrap().to_rfc3339();
+        // dates seen in real feeds
+        assert_eq!(
+            parsed("Mon, 2 Jan 2006 15:04 MST"),
+            "2006-01-02T15:04:00-07:00"
+        );
+        assert_eq!(
+            parsed("Tue, 02 Jan 2006 15:04:05 +0000"),
+            "2006-01-02T15:04:05+00:00"
+        );
+        assert_eq!(
+            parsed("Monday, 02 January 2006 15:04:05 CEST"),
+            "2006-01-02T15:04:05+02:00"
+        );
+        assert_eq!(parsed("2006-01-02T15:04:05"), "2006-01-02T15:04:05+00:00");
+        assert_eq!(
+            parsed("2006-01-02 15:04:05 +0300"),
+            "2006-01-02T15:04:05+03:00"
+        );
+        assert_eq!(parsed("2006-01-02"), "2006-01-02T00:00:00+00:00");
+        assert!(parse_date("yesterday").is_err());
+    }
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("90d"), Ok(TimeDelta::days(90)));
+        assert_eq!(parse_duration("48h"), Ok(TimeDelta::hours(48)));
+        assert_eq!(parse_duration("15m"), Ok(TimeDelta::minutes(15)));
+        assert_eq!(parse_duration("500ms"), Ok(TimeDelta::milliseconds(500)));
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert_eq!(
+            parse_duration("99999999999999999d"),
+            Err("Duration '99999999999999999d' out of range".to_owned())
+        );
+        assert!(parse_duration("99999999999999w").is_err());
+        assert!(parse_duration("15000000000w").is_err());
+    }
+
+    #[test]
+    fn format_duration_round_trips() {
+        for text in ["500ms", "90s", "15m", "2h", "3d", "2w", "0ms"] {
+            assert_eq!(format_duration(parse_duration(text).unwrap()), text);
+        }
+        assert_eq!(format_duration(TimeDelta::seconds(120)), "2m");
+        assert_eq!(format_duration(TimeDelta::milliseconds(1500)), "1500ms");
+    }
+
+    #[test]
+    fn parse_date_bound_forms() {
+        let now = DateTime::parse_from_rfc3339("2024-06-01T12:00:00+02:00").unwrap();
+        let resolve = |raw| parse_date_bound(raw).map(|bound| bound.resolve(now).to_rfc3339());
+        assert_eq!(resolve("2d"), Ok("2024-05-30T12:00:00+02:00".to_owned()));
+        assert_eq!(
+            resolve("2024-01-15"),
+            Ok("2024-01-15T00:00:00+02:00".to_owned())
+        );
+        assert_eq!(
+            resolve("2024-01-15T08:00:00Z"),
+            Ok("2024-01-15T08:00:00+00:00".to_owned())
+        );
+        assert!(parse_date_bound("last week").is_err());
+    }
+}
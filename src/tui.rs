@@ -0,0 +1,348 @@
+//! Full screen list of items for `fdr tui`, drawn with ANSI escapes on a terminal
+//! in raw mode
+use colored::*;
+use std::io::{self, Read, Write};
+
+const HELP: &str = "j/k move  enter preview  J/K scroll  r read/unread  o open  s source  q quit";
+
+/// Terminal in raw mode on the alternate screen, restored when dropped
+struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    fn enter() -> io::Result<Self> {
+        let mut original = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = original;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+        Ok(RawTerminal { original })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// Columns and rows of the terminal, 80x24 when it can't be told
+fn terminal_size() -> (usize, usize) {
+    let mut size = unsafe { std::mem::zeroed::<libc::winsize>() };
+    let found = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    if found && size.ws_col > 0 && size.ws_row > 0 {
+        (usize::from(size.ws_col), usize::from(size.ws_row))
+    } else {
+        (80, 24)
+    }
+}
+
+enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Char(char),
+    Other,
+}
+
+/// Reads a key press. Escape sequences of a key arrive in a single read
+fn read_key() -> io::Result<Key> {
+    let mut buf = [0; 16];
+    let read = io::stdin().read(&mut buf)?;
+    Ok(match &buf[..read] {
+        b"\x1b[A" | b"\x1bOA" => Key::Up,
+        b"\x1b[B" | b"\x1bOB" => Key::Down,
+        b"\x1b[5~" => Key::PageUp,
+        b"\x1b[6~" => Key::PageDown,
+        b"\x1b[H" | b"\x1b[1~" => Key::Home,
+        b"\x1b[F" | b"\x1b[4~" => Key::End,
+        b"\r" | b"\n" => Key::Enter,
+        b"\x1b" => Key::Char('q'),
+        [byte] if byte.is_ascii() => Key::Char(char::from(*byte)),
+        _ => Key::Other,
+    })
+}
+
+/// Cuts text to `width` characters, the terminal being assumed to show one cell each
+fn fit(text: &str, width: usize) -> String {
+    text.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(width)
+        .collect()
+}
+
+/// Splits text into lines of at most `width` characters, breaking at spaces
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let length = line.chars().count();
+            if length > 0 && length + 1 + word.chars().count() > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// State of the screen. Items are kept in one list, the source filter picks the visible ones
+struct App<'a> {
+    items: Vec<fdr::FeedItem>,
+    seen: &'a mut fdr::SeenStore,
+    now: chrono::DateTime<chrono::FixedOffset>,
+    locale: fdr::Locale,
+    sources: Vec<String>,
+    /// Index into `sources`, `None` shows items of all sources
+    source: Option<usize>,
+    /// Position of the selected item among the visible ones
+    selected: usize,
+    /// First visible item shown at the top of the list
+    offset: usize,
+    preview: bool,
+    preview_offset: usize,
+    /// Shown in the footer until the next key press
+    message: Option<String>,
+}
+
+impl App<'_> {
+    fn visible(&self) -> Vec<usize> {
+        let source = self.source.map(|index| &self.sources[index]);
+        (0..self.items.len())
+            .filter(|&index| source.is_none_or(|source| self.items[index].source_name == *source))
+            .collect()
+    }
+
+    fn selected_item(&self) -> Option<&fdr::FeedItem> {
+        let index = *self.visible().get(self.selected)?;
+        Some(&self.items[index])
+    }
+
+    fn is_read(&self, item: &fdr::FeedItem) -> bool {
+        self.seen.contains_item(item)
+    }
+
+    fn mark_read(&mut self) {
+        let Some(index) = self.visible().get(self.selected).copied() else {
+            return;
+        };
+        self.seen.insert_item(&self.items[index], self.now);
+    }
+
+    fn toggle_read(&mut self) {
+        let Some(index) = self.visible().get(self.selected).copied() else {
+            return;
+        };
+        let item = &self.items[index];
+        if !self.seen.remove_item(item) {
+            self.seen.insert_item(item, self.now);
+        }
+    }
+
+    fn select(&mut self, position: usize) {
+        let count = self.visible().len();
+        self.selected = position.min(count.saturating_sub(1));
+        self.preview_offset = 0;
+    }
+
+    fn next_source(&mut self) {
+        self.source = match self.source {
+            None if !self.sources.is_empty() => Some(0),
+            Some(index) if index + 1 < self.sources.len() => Some(index + 1),
+            _ => None,
+        };
+        self.select(0);
+    }
+
+    /// Rows of the list, the rest below the header and footer goes to the preview
+    fn list_height(&self, rows: usize) -> usize {
+        let body = rows.saturating_sub(2).max(1);
+        if self.preview {
+            (body / 2).max(1)
+        } else {
+            body
+        }
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let (columns, rows) = terminal_size();
+        let visible = self.visible();
+        let height = self.list_height(rows);
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + height {
+            self.offset = self.selected + 1 - height;
+        }
+        let unread = visible
+            .iter()
+            .filter(|&&index| !self.is_read(&self.items[index]))
+            .count();
+        let source = self
+            .source
+            .map_or("all sources", |index| self.sources[index].as_str());
+        let mut screen = String::from("\x1b[H");
+        let header = format!(
+            "fdr: {} items, {} unread, {}",
+            visible.len(),
+            unread,
+            source
+        );
+        screen.push_str(&format!("{}\x1b[K\r\n", fit(&header, columns).bold()));
+        for row in 0..height {
+            if let Some(&index) = visible.get(self.offset + row) {
+                let item = &self.items[index];
+                let marker = if self.is_read(item) { ' ' } else { '*' };
+                let date = item.pub_date.map_or("date unknown".to_owned(), |date| {
+                    fdr::date_diff(self.now - date, self.locale)
+                });
+                let line = fit(
+                    &format!("{} {}: {} ({})", marker, item.source_name, item.title, date),
+                    columns,
+                );
+                // the selection is reversed even without colors, it'd be lost otherwise
+                let line = match (self.offset + row == self.selected, self.is_read(item)) {
+                    (true, _) => format!("\x1b[7m{}\x1b[0m", line),
+                    (false, true) => line.dimmed().to_string(),
+                    (false, false) => line,
+                };
+                screen.push_str(&line);
+            }
+            screen.push_str("\x1b[K\r\n");
+        }
+        if self.preview {
+            let preview_height = rows.saturating_sub(height + 2);
+            let text = self.selected_item().map(preview_text).unwrap_or_default();
+            let lines = wrap(&text, columns.max(1));
+            self.preview_offset = self
+                .preview_offset
+                .min(lines.len().saturating_sub(preview_height));
+            for row in 0..preview_height {
+                if row == 0 {
+                    screen.push_str(&format!("{}", "─".repeat(columns).dimmed()));
+                } else if let Some(line) = lines.get(self.preview_offset + row - 1) {
+                    screen.push_str(line);
+                }
+                screen.push_str("\x1b[K\r\n");
+            }
+        }
+        let footer = self.message.take().unwrap_or_else(|| HELP.to_owned());
+        screen.push_str(&format!("{}\x1b[K", fit(&footer, columns).dimmed()));
+        print!("{}", screen);
+        io::stdout().flush()
+    }
+}
+
+/// Plain text of the item shown in the preview pane
+fn preview_text(item: &fdr::FeedItem) -> String {
+    let body = match (&item.content, &item.description) {
+        (Some(content), _) => fdr::html_to_text(content),
+        (None, Some(description)) => description.clone(),
+        (None, None) => "No content".to_owned(),
+    };
+    format!("{}\n{}\n\n{}", item.title, item.link, body)
+}
+
+/// Shows `items` until the user quits, marking items read in `seen` as they
+/// are previewed, opened or toggled
+pub fn run(
+    items: Vec<fdr::FeedItem>,
+    seen: &mut fdr::SeenStore,
+    now: chrono::DateTime<chrono::FixedOffset>,
+    locale: fdr::Locale,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::IsTerminal;
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err("fdr tui needs a terminal".into());
+    }
+    let mut sources: Vec<String> = Vec::new();
+    for item in &items {
+        if !sources.contains(&item.source_name) {
+            sources.push(item.source_name.clone());
+        }
+    }
+    let mut app = App {
+        items,
+        seen,
+        now,
+        locale,
+        sources,
+        source: None,
+        selected: 0,
+        offset: 0,
+        preview: false,
+        preview_offset: 0,
+        message: None,
+    };
+    let mut terminal = Some(RawTerminal::enter()?);
+    loop {
+        app.draw()?;
+        let page = app.list_height(terminal_size().1);
+        match read_key()? {
+            Key::Char('q') => break,
+            Key::Up | Key::Char('k') => app.select(app.selected.saturating_sub(1)),
+            Key::Down | Key::Char('j') => app.select(app.selected + 1),
+            Key::PageUp => app.select(app.selected.saturating_sub(page)),
+            Key::PageDown | Key::Char(' ') => app.select(app.selected + page),
+            Key::Home | Key::Char('g') => app.select(0),
+            Key::End | Key::Char('G') => app.select(usize::MAX),
+            Key::Enter | Key::Char('p') => {
+                app.preview = !app.preview;
+                app.preview_offset = 0;
+                if app.preview {
+                    app.mark_read();
+                }
+            }
+            Key::Char('J') => app.preview_offset += 1,
+            Key::Char('K') => app.preview_offset = app.preview_offset.saturating_sub(1),
+            Key::Char('r') => app.toggle_read(),
+            Key::Char('s') => app.next_source(),
+            Key::Char('o') => {
+                let Some(link) = app.selected_item().map(|item| item.link.clone()) else {
+                    continue;
+                };
+                // terminal browsers need the terminal back
+                drop(terminal.take());
+                let opened = crate::open_in_browser(&link);
+                terminal = Some(RawTerminal::enter()?);
+                match opened {
+                    Ok(()) => app.mark_read(),
+                    Err(err) => app.message = Some(err.to_string()),
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_breaks_at_spaces() {
+        assert_eq!(
+            wrap("one two three\n\nfour", 8),
+            vec!["one two", "three", "", "four"]
+        );
+        assert_eq!(fit("tab\there", 5), "tab h");
+    }
+}
@@ -0,0 +1,385 @@
+//! Time zones by name, read from the system tz database (TZif files, RFC 8536)
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, TimeDelta, Utc};
+use std::path::PathBuf;
+
+/// Time zone to show dates in
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimeZone {
+    Fixed(FixedOffset),
+    Zone(ZoneInfo),
+}
+
+/// Offsets of a tz database zone
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZoneInfo {
+    /// Unix times of offset changes with the offsets in seconds from then on
+    transitions: Vec<(i64, i32)>,
+    /// Offset before the first transition
+    initial: i32,
+    /// Rule for times after the last transition
+    rule: Option<PosixRule>,
+}
+
+/// Time zone in POSIX `TZ` format, e.g. `CET-1CEST,M3.5.0,M10.5.0/3`
+#[derive(Debug, Clone, PartialEq)]
+struct PosixRule {
+    std: i32,
+    /// Offset with daylight saving time and when it starts and ends, in local time
+    dst: Option<(i32, RuleDate, RuleDate)>,
+}
+
+/// Day and time of year given as `Mm.w.d/time`: weekday `d` (0 is Sunday)
+/// of week `w` (5 is the last one) of month `m`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RuleDate {
+    month: u32,
+    week: u32,
+    weekday: u32,
+    /// Seconds since local midnight, may be negative or past a day
+    time: i64,
+}
+
+impl TimeZone {
+    /// Finds time zone by name: `UTC`, offset like `+05:30`, or tz database name like
+    /// `Europe/Berlin`, read from `$TZDIR` or `/usr/share/zoneinfo`
+    pub fn lookup(name: &str) -> Result<Self, String> {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("utc") || name == "Z" {
+            return Ok(TimeZone::Fixed(FixedOffset::east_opt(0).unwrap()));
+        }
+        if name.starts_with(['+', '-']) {
+            return parse_fixed_offset(name)
+                .map(TimeZone::Fixed)
+                .ok_or_else(|| format!("Invalid UTC offset '{}', expected e.g. +05:30", name));
+        }
+        if name.is_empty() || name.starts_with('/') || name.split('/').any(|part| part == "..") {
+            return Err(format!("Invalid time zone name '{}'", name));
+        }
+        let dir = std::env::var_os("TZDIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("/usr/share/zoneinfo"));
+        let data = std::fs::read(dir.join(name))
+            .map_err(|err| format!("Unknown time zone '{}': {}", name, err))?;
+        ZoneInfo::parse(&data)
+            .map(TimeZone::Zone)
+            .map_err(|err| format!("Can't read time zone '{}': {}", name, err))
+    }
+
+    /// Offset from UTC in effect at `time`
+    pub fn offset_at(&self, time: DateTime<Utc>) -> FixedOffset {
+        let seconds = match self {
+            TimeZone::Fixed(offset) => return *offset,
+            TimeZone::Zone(zone) => zone.offset_at(time.timestamp()),
+        };
+        FixedOffset::east_opt(seconds).unwrap_or(FixedOffset::east_opt(0).unwrap())
+    }
+
+    /// Converts `time` to this time zone
+    pub fn convert<Tz: chrono::TimeZone>(&self, time: &DateTime<Tz>) -> DateTime<FixedOffset> {
+        let utc = time.with_timezone(&Utc);
+        utc.with_timezone(&self.offset_at(utc))
+    }
+}
+
+/// Parses `+05:30`, `+0530` or `+05`
+fn parse_fixed_offset(text: &str) -> Option<FixedOffset> {
+    let (sign, digits) = match text.split_at(1) {
+        ("+", rest) => (1, rest),
+        ("-", rest) => (-1, rest),
+        _ => return None,
+    };
+    let digits = digits.replace(':', "");
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (
+            digits[..2].parse::<i32>().ok()?,
+            digits[2..].parse::<i32>().ok()?,
+        ),
+        _ => return None,
+    };
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+impl ZoneInfo {
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let header = Header::parse(data)?;
+        // version 2+ files repeat the data with 64-bit times after the 32-bit block
+        let (header, block, time_size) = if header.version >= b'2' {
+            let rest = data.get(header.block_len(4)..).ok_or("file is truncated")?;
+            (Header::parse(rest)?, &rest[44..], 8)
+        } else {
+            (header, &data[44..], 4)
+        };
+        let truncated = || "file is truncated".to_owned();
+        let times_len = header.transitions * time_size;
+        let types_start = times_len + header.transitions;
+        let block_end = header.block_len(time_size) - 44;
+        if block.len() < block_end {
+            return Err(truncated());
+        }
+        let offsets: Vec<i32> = block[types_start..types_start + header.types * 6]
+            .chunks(6)
+            .map(|info| i32::from_be_bytes([info[0], info[1], info[2], info[3]]))
+            .collect();
+        if offsets.is_empty() {
+            return Err("no local time types".to_owned());
+        }
+        let mut transitions = Vec::with_capacity(header.transitions);
+        for i in 0..header.transitions {
+            let time = &block[i * time_size..(i + 1) * time_size];
+            let time = match time_size {
+                8 => i64::from_be_bytes(time.try_into().unwrap()),
+                _ => i64::from(i32::from_be_bytes(time.try_into().unwrap())),
+            };
+            let index = block[times_len + i] as usize;
+            let offset = *offsets.get(index).ok_or("invalid local time type index")?;
+            transitions.push((time, offset));
+        }
+        // times before the first transition use the first local time type
+        let initial = offsets[0];
+        let rule = match header.version {
+            b'2'.. => {
+                let footer = String::from_utf8_lossy(&block[block_end..]);
+                let footer = footer.trim_matches('\n');
+                if footer.is_empty() {
+                    None
+                } else {
+                    Some(PosixRule::parse(footer)?)
+                }
+            }
+            _ => None,
+        };
+        Ok(ZoneInfo {
+            transitions,
+            initial,
+            rule,
+        })
+    }
+
+    fn offset_at(&self, time: i64) -> i32 {
+        let count = self
+            .transitions
+            .partition_point(|(transition, _)| *transition <= time);
+        match (count, &self.rule) {
+            (0, _) => self.initial,
+            (count, Some(rule)) if count == self.transitions.len() => rule.offset_at(time),
+            (count, _) => self.transitions[count - 1].1,
+        }
+    }
+}
+
+/// Counts of TZif data block items
+struct Header {
+    version: u8,
+    utc_indicators: usize,
+    std_indicators: usize,
+    leap_seconds: usize,
+    transitions: usize,
+    types: usize,
+    chars: usize,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Result<Self, String> {
+        if data.len() < 44 || !data.starts_with(b"TZif") {
+            return Err("not a TZif file".to_owned());
+        }
+        let count = |i: usize| {
+            let at = 20 + i * 4;
+            u32::from_be_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]) as usize
+        };
+        Ok(Header {
+            version: data[4],
+            utc_indicators: count(0),
+            std_indicators: count(1),
+            leap_seconds: count(2),
+            transitions: count(3),
+            types: count(4),
+            chars: count(5),
+        })
+    }
+
+    /// Length of header with its data block for times of `time_size` bytes
+    fn block_len(&self, time_size: usize) -> usize {
+        44 + self.transitions * (time_size + 1)
+            + self.types * 6
+            + self.chars
+            + self.leap_seconds * (time_size + 4)
+            + self.std_indicators
+            + self.utc_indicators
+    }
+}
+
+impl PosixRule {
+    fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid TZ rule '{}'", text);
+        let mut rest = text;
+        skip_name(&mut rest).ok_or_else(invalid)?;
+        // POSIX offsets are west of UTC, so `CET-1` is an hour ahead of UTC
+        let std = -parse_rule_time(&mut rest).ok_or_else(invalid)? as i32;
+        if rest.is_empty() {
+            return Ok(PosixRule { std, dst: None });
+        }
+        skip_name(&mut rest).ok_or_else(invalid)?;
+        let dst = match rest.starts_with(',') {
+            true => std + 3600,
+            false => -parse_rule_time(&mut rest).ok_or_else(invalid)? as i32,
+        };
+        let mut dates = rest.strip_prefix(',').ok_or_else(invalid)?.split(',');
+        let start = dates.next().and_then(RuleDate::parse).ok_or_else(invalid)?;
+        let end = dates.next().and_then(RuleDate::parse).ok_or_else(invalid)?;
+        Ok(PosixRule {
+            std,
+            dst: Some((dst, start, end)),
+        })
+    }
+
+    fn offset_at(&self, time: i64) -> i32 {
+        let Some((dst, start, end)) = self.dst else {
+            return self.std;
+        };
+        let Some(year) = DateTime::from_timestamp(time + i64::from(self.std), 0).map(|t| t.year())
+        else {
+            return self.std;
+        };
+        // start is given in standard time and end in daylight saving time
+        let (Some(start), Some(end)) = (start.timestamp(year, self.std), end.timestamp(year, dst))
+        else {
+            return self.std;
+        };
+        let in_dst = match start < end {
+            true => start <= time && time < end,
+            // southern hemisphere, daylight saving time spans the new year
+            false => time >= start || time < end,
+        };
+        if in_dst {
+            dst
+        } else {
+            self.std
+        }
+    }
+}
+
+impl RuleDate {
+    /// Parses `Mm.w.d[/time]`. Julian day forms aren't used by the tz database rules
+    fn parse(text: &str) -> Option<Self> {
+        let (date, time) = match text.split_once('/') {
+            Some((date, mut time)) => (
+                date,
+                parse_rule_time(&mut time).filter(|_| time.is_empty())?,
+            ),
+            None => (text, 2 * 3600),
+        };
+        let mut parts = date.strip_prefix('M')?.split('.');
+        let mut next = || parts.next()?.parse::<u32>().ok();
+        let (month, week, weekday) = (next()?, next()?, next()?);
+        if !(1..=12).contains(&month) || !(1..=5).contains(&week) || weekday > 6 {
+            return None;
+        }
+        Some(RuleDate {
+            month,
+            week,
+            weekday,
+            time,
+        })
+    }
+
+    /// Unix time of the date in `year` with local time `offset` seconds east of UTC
+    fn timestamp(&self, year: i32, offset: i32) -> Option<i64> {
+        let first = NaiveDate::from_ymd_opt(year, self.month, 1)?;
+        let first_weekday = first.weekday().num_days_from_sunday();
+        let mut day = 1 + (self.weekday + 7 - first_weekday) % 7 + (self.week - 1) * 7;
+        while NaiveDate::from_ymd_opt(year, self.month, day).is_none() {
+            day -= 7;
+        }
+        let midnight = NaiveDate::from_ymd_opt(year, self.month, day)?.and_hms_opt(0, 0, 0)?;
+        let local = midnight + TimeDelta::seconds(self.time);
+        Some(local.and_utc().timestamp() - i64::from(offset))
+    }
+}
+
+/// Skips zone abbreviation, either letters like `CEST` or quoted like `<+03>`
+fn skip_name(text: &mut &str) -> Option<()> {
+    let end = match text.strip_prefix('<') {
+        Some(quoted) => quoted.find('>')? + 2,
+        None => text
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(text.len()),
+    };
+    if end < 3 {
+        return None;
+    }
+    *text = &text[end..];
+    Some(())
+}
+
+/// Parses `[+-]hh[:mm[:ss]]` into seconds
+fn parse_rule_time(text: &mut &str) -> Option<i64> {
+    let end = text
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, ':' | '+' | '-')))
+        .unwrap_or(text.len());
+    let (time, rest) = text.split_at(end);
+    // a sign may only lead, e.g. in `EST5EDT` the offset ends before `E`
+    let (sign, time) = match time.split_at_checked(1) {
+        Some(("-", time)) => (-1, time),
+        Some(("+", time)) => (1, time),
+        _ => (1, time),
+    };
+    let mut seconds = 0;
+    for (i, part) in time.split(':').enumerate() {
+        if i > 2 || part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        seconds += part.parse::<i64>().ok()? * [3600, 60, 1][i];
+    }
+    *text = rest;
+    Some(sign * seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offset_on(rule: &PosixRule, date: &str) -> i32 {
+        let time = DateTime::parse_from_rfc3339(date).unwrap();
+        rule.offset_at(time.timestamp())
+    }
+
+    #[test]
+    fn posix_rule_switches_on_transitions() {
+        let rule = PosixRule::parse("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        assert_eq!(offset_on(&rule, "2030-01-15T12:00:00Z"), 3600);
+        // last Sunday of March 2030 is the 31st, the switch is at 01:00 UTC
+        assert_eq!(offset_on(&rule, "2030-03-31T00:59:59Z"), 3600);
+        assert_eq!(offset_on(&rule, "2030-03-31T01:00:00Z"), 7200);
+        assert_eq!(offset_on(&rule, "2030-10-27T00:59:59Z"), 7200);
+        assert_eq!(offset_on(&rule, "2030-10-27T01:00:00Z"), 3600);
+
+        let southern = PosixRule::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(offset_on(&southern, "2030-01-15T00:00:00Z"), 11 * 3600);
+        assert_eq!(offset_on(&southern, "2030-07-15T00:00:00Z"), 10 * 3600);
+
+        let fixed = PosixRule::parse("<+0530>-5:30").unwrap();
+        assert_eq!(offset_on(&fixed, "2030-07-15T00:00:00Z"), 5 * 3600 + 1800);
+        assert!(PosixRule::parse("EST5EDT").is_err());
+    }
+
+    #[test]
+    fn lookup_fixed_offsets() {
+        let offset = |name| match TimeZone::lookup(name) {
+            Ok(TimeZone::Fixed(offset)) => Some(offset.local_minus_utc()),
+            _ => None,
+        };
+        assert_eq!(offset("UTC"), Some(0));
+        assert_eq!(offset("+05:30"), Some(5 * 3600 + 1800));
+        assert_eq!(offset("-0800"), Some(-8 * 3600));
+        assert_eq!(offset("+5"), None);
+        assert!(TimeZone::lookup("../etc/passwd").is_err());
+    }
+}
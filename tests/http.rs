@@ -0,0 +1,363 @@
+//! Fetching feeds from a local server that answers with canned responses
+use fdr::{ClientOptions, Error, FeedClient, HttpCache};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const RSS: &str = r#"<?xml version="1.0"?>
+<rss version="2.0"><channel>
+  <title>Mock RSS</title><link>http://example.com/</link><description>Mock</description>
+  <item>
+    <title>First</title><link>http://example.com/1</link>
+    <pubDate>Mon, 06 May 2024 10:00:00 +0000</pubDate>
+  </item>
+  <item><title>Second</title><link>http://example.com/2</link></item>
+</channel></rss>"#;
+
+const ATOM: &str = r#"<?xml version="1.0"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Mock Atom</title><id>urn:mock</id><updated>2024-05-06T10:00:00Z</updated>
+  <entry>
+    <title>Entry</title><id>urn:mock:1</id><link href="http://example.com/a"/>
+    <updated>2024-05-06T10:00:00Z</updated>
+  </entry>
+</feed>"#;
+
+/// Request received by [`MockServer`]
+struct Request {
+    path: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn new(status: u16) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    fn ok(body: &str) -> Self {
+        Response::new(200).body(body)
+    }
+
+    fn redirect(status: u16, location: &str) -> Self {
+        Response::new(status).header("Location", location)
+    }
+
+    fn header(mut self, name: &'static str, value: &str) -> Self {
+        self.headers.push((name, value.to_owned()));
+        self
+    }
+
+    fn body(mut self, body: &str) -> Self {
+        self.body = body.as_bytes().to_vec();
+        self
+    }
+}
+
+type Handler = dyn Fn(&Request) -> Response + Send + Sync;
+
+/// HTTP/1.1 server on a free local port answering every request with `handler`,
+/// one request per connection
+struct MockServer {
+    port: u16,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer {
+    async fn start(handler: impl Fn(&Request) -> Response + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+        let received = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let (handler, received) = (handler.clone(), received.clone());
+                tokio::spawn(async move {
+                    let mut head = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+                        match stream.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(read) => head.extend_from_slice(&buf[..read]),
+                        }
+                    }
+                    let request = parse_request(&String::from_utf8_lossy(&head));
+                    let response = handler(&request);
+                    received.lock().unwrap().push(request);
+                    let mut raw = format!("HTTP/1.1 {} Mock\r\n", response.status);
+                    for (name, value) in &response.headers {
+                        raw.push_str(&format!("{}: {}\r\n", name, value));
+                    }
+                    raw.push_str(&format!(
+                        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+                        response.body.len()
+                    ));
+                    let mut raw = raw.into_bytes();
+                    raw.extend_from_slice(&response.body);
+                    let _ = stream.write_all(&raw).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+        MockServer { port, requests }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, path)
+    }
+
+    /// Paths of the requests received so far
+    fn paths(&self) -> Vec<String> {
+        let requests = self.requests.lock().unwrap();
+        requests
+            .iter()
+            .map(|request| request.path.clone())
+            .collect()
+    }
+}
+
+fn parse_request(head: &str) -> Request {
+    let mut lines = head.lines();
+    let path = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .unwrap_or_default()
+        .to_owned();
+    let headers = lines
+        .map_while(|line| line.split_once(": "))
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect();
+    Request { path, headers }
+}
+
+fn client(retries: u32) -> FeedClient {
+    let options = ClientOptions {
+        retries,
+        per_host_interval: std::time::Duration::ZERO,
+        ..ClientOptions::default()
+    };
+    fdr::build_client(&options).unwrap()
+}
+
+/// Cache in a fresh directory, removed when the guard is dropped
+struct TempCache(std::path::PathBuf);
+
+impl TempCache {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("fdr-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        TempCache(dir)
+    }
+
+    fn cache(&self) -> HttpCache {
+        HttpCache::new(self.0.clone())
+    }
+}
+
+impl Drop for TempCache {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[tokio::test]
+async fn reads_rss_and_atom_items() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/rss" => Response::ok(RSS).header("Content-Type", "application/rss+xml"),
+        "/atom" => Response::ok(ATOM),
+        _ => Response::new(404),
+    })
+    .await;
+    let client = client(0);
+
+    let url = server.url("/rss");
+    let fetched = fdr::read_feed(&client, &url).await.unwrap();
+    assert_eq!(fetched.feed.title(), "Mock RSS");
+    assert_eq!(fetched.moved_to, None);
+    let read = fdr::read_feed_items(&fetched.feed, Some("Mock"), &url);
+    assert!(read.errors.is_empty());
+    let titles: Vec<&str> = read.items.iter().map(|item| item.title.as_str()).collect();
+    assert_eq!(titles, ["First", "Second"]);
+    assert_eq!(read.items[0].source_name, "Mock");
+    assert!(read.items[0].pub_date.is_some());
+
+    let url = server.url("/atom");
+    let fetched = fdr::read_feed(&client, &url).await.unwrap();
+    let read = fdr::read_feed_items(&fetched.feed, None, &url);
+    assert_eq!(read.items[0].link, "http://example.com/a");
+    assert_eq!(read.items[0].source_name, "Mock Atom");
+}
+
+#[tokio::test]
+async fn follows_redirects_and_reports_permanent_moves() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/old" => Response::redirect(301, "/moved"),
+        "/moved" => Response::redirect(302, "/today"),
+        "/today" => Response::ok(RSS),
+        "/loop" => Response::redirect(302, "/loop"),
+        _ => Response::new(404),
+    })
+    .await;
+    let client = client(0);
+
+    let fetched = fdr::read_feed(&client, &server.url("/old")).await.unwrap();
+    assert_eq!(fetched.feed.title(), "Mock RSS");
+    // the temporary redirect isn't where the feed lives now
+    assert_eq!(fetched.moved_to, Some(server.url("/moved")));
+    assert_eq!(server.paths(), ["/old", "/moved", "/today"]);
+
+    let response = fdr::fetch_feed(&client, &server.url("/old")).await.unwrap();
+    let hops: Vec<(u16, &str)> = response
+        .redirects
+        .iter()
+        .map(|redirect| (redirect.status.as_u16(), redirect.location.as_str()))
+        .collect();
+    let (moved, today) = (server.url("/moved"), server.url("/today"));
+    assert_eq!(hops, [(301, moved.as_str()), (302, today.as_str())]);
+    assert_eq!(response.url, today);
+
+    let result = fdr::read_feed(&client, &server.url("/loop")).await;
+    assert!(matches!(result, Err(Error::TooManyRedirects(10))));
+    let options = ClientOptions {
+        max_redirects: 1,
+        per_host_interval: std::time::Duration::ZERO,
+        ..ClientOptions::default()
+    };
+    let client = fdr::build_client(&options).unwrap();
+    let result = fdr::read_feed(&client, &server.url("/old")).await;
+    assert!(matches!(result, Err(Error::TooManyRedirects(1))));
+}
+
+#[tokio::test]
+async fn error_statuses_fail_and_server_errors_are_retried() {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/gone" => Response::new(404).body("<html>Not found</html>"),
+        _ => Response::new(503),
+    })
+    .await;
+    let client = client(1);
+
+    let result = fdr::read_feed(&client, &server.url("/gone")).await;
+    let Err(Error::HttpStatus { url, status }) = result else {
+        panic!("expected an HTTP status error");
+    };
+    assert_eq!((url, status.as_u16()), (server.url("/gone"), 404));
+
+    let result = fdr::read_feed(&client, &server.url("/down")).await;
+    assert!(matches!(result, Err(Error::HttpStatus { status, .. }) if status == 503));
+    assert_eq!(server.paths(), ["/gone", "/down", "/down"]);
+}
+
+#[tokio::test]
+async fn invalid_feeds_fail_to_parse() {
+    let server = MockServer::start(|_| Response::ok("<html><body>Not a feed</body></html>")).await;
+    let result = fdr::read_feed(&client(0), &server.url("/page")).await;
+    assert!(matches!(result, Err(Error::FeedParse(_))));
+}
+
+#[tokio::test]
+async fn cache_sends_validators_and_reuses_body_when_not_modified() {
+    let server = MockServer::start(|request| {
+        if request.header("If-None-Match") == Some("\"v1\"") {
+            Response::new(304)
+        } else {
+            Response::ok(RSS).header("ETag", "\"v1\"")
+        }
+    })
+    .await;
+    let client = client(0);
+    let temp = TempCache::new("not-modified");
+    let cache = temp.cache();
+    let url = server.url("/feed");
+
+    let first = fdr::read_feed_cached(&client, &url, &cache).await.unwrap();
+    let second = fdr::read_feed_cached(&client, &url, &cache).await.unwrap();
+    assert_eq!(first.feed.title(), "Mock RSS");
+    assert_eq!(second.feed.title(), "Mock RSS");
+    let requests = server.requests.lock().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].header("If-None-Match"), None);
+    assert_eq!(requests[1].header("If-None-Match"), Some("\"v1\""));
+}
+
+#[tokio::test]
+async fn cache_ttl_and_offline_skip_requests() {
+    let server = MockServer::start(|_| Response::ok(ATOM)).await;
+    let client = client(0);
+    let temp = TempCache::new("ttl");
+    let url = server.url("/feed");
+
+    let result = fdr::read_feed_cached(&client, &url, &temp.cache().offline()).await;
+    assert!(matches!(result, Err(Error::NotCached(_))));
+    assert!(server.paths().is_empty());
+
+    let cache = temp.cache().with_ttl(std::time::Duration::from_secs(3600));
+    fdr::read_feed_cached(&client, &url, &cache).await.unwrap();
+    fdr::read_feed_cached(&client, &url, &cache).await.unwrap();
+    let offline = fdr::read_feed_cached(&client, &url, &temp.cache().offline()).await;
+    assert_eq!(offline.unwrap().feed.title(), "Mock Atom");
+    assert_eq!(server.paths(), ["/feed"]);
+}
+
+#[tokio::test]
+async fn requests_to_a_host_are_spaced_out() {
+    let server = MockServer::start(|_| Response::ok(RSS)).await;
+    let options = ClientOptions {
+        per_host_concurrency: 1,
+        per_host_interval: std::time::Duration::from_millis(200),
+        ..ClientOptions::default()
+    };
+    let client = fdr::build_client(&options).unwrap();
+    let urls: Vec<String> = (1..=3).map(|n| server.url(&format!("/{}", n))).collect();
+    let started = std::time::Instant::now();
+    let results =
+        futures_util::future::join_all(urls.iter().map(|url| fdr::read_feed(&client, url))).await;
+    assert!(results.iter().all(Result::is_ok));
+    assert!(started.elapsed() >= std::time::Duration::from_millis(400));
+    assert_eq!(server.paths().len(), 3);
+}
+
+#[tokio::test]
+async fn rate_limited_requests_wait_for_retry_after() {
+    let requests = std::sync::atomic::AtomicUsize::new(0);
+    let server = MockServer::start(move |_| {
+        if requests.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+            Response::new(429).header("Retry-After", "1")
+        } else {
+            Response::ok(RSS)
+        }
+    })
+    .await;
+    let started = std::time::Instant::now();
+    let fetched = fdr::read_feed(&client(1), &server.url("/feed"))
+        .await
+        .unwrap();
+    assert_eq!(fetched.feed.title(), "Mock RSS");
+    assert!(started.elapsed() >= std::time::Duration::from_secs(1));
+    assert_eq!(server.paths().len(), 2);
+
+    // waiting longer than a minute isn't worth it
+    let server = MockServer::start(|_| Response::new(429).header("Retry-After", "3600")).await;
+    let result = fdr::read_feed(&client(1), &server.url("/feed")).await;
+    assert!(matches!(result, Err(Error::HttpStatus { status, .. }) if status == 429));
+    assert_eq!(server.paths().len(), 1);
+}